@@ -10,11 +10,9 @@ async fn main() -> Result<(), Error> {
         .proxy("socks5h://127.0.0.1:9050") // Add optional proxy
         .build_async()?; // Build dispatcher
 
-    let action = Action::new(
-        ActionType::Http,
-        "Turn down",
-        Url::parse("https://api.nest.com")?,
-    );
+    let action = Action::http("Turn down", Url::parse("https://api.nest.com")?)
+        .method("PUT")
+        .body(r#"{"temperature": 65}"#);
 
     let payload = Payload::new("mytopic")
         .message("Hello, **World**!") // Add optional message
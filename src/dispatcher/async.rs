@@ -1,20 +1,43 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use std::future::Future;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+use futures_timer::Delay;
+use futures_util::stream::{self, StreamExt};
 #[cfg(all(feature = "socks", not(target_arch = "wasm32")))]
 use reqwest::Proxy;
 use reqwest::header::{HeaderMap, HeaderValue};
-use reqwest::{Client, ClientBuilder, Response};
+use reqwest::{Body, Client, ClientBuilder, Response};
 use url::Url;
 
-use super::builder::DispatcherBuilder;
+use super::builder::{DispatcherBuilder, PublishMode};
+#[cfg(not(target_arch = "wasm32"))]
+use super::dedup::{Deduplicator, Reservation};
+#[cfg(not(target_arch = "wasm32"))]
+use super::limiter::RateLimiter;
+use super::oversized::OversizedPolicy;
+use super::request::{self, Prepared};
+use super::retry::RetryPolicy;
 use crate::error::Error;
-use crate::payload::Payload;
+use crate::payload::validation::Encoding;
+use crate::payload::{Payload, PublishedMessage, ValidationPolicy};
 
 /// Async dispatcher
 #[derive(Debug, Clone)]
 pub struct Async {
     client: Client,
+    mode: PublishMode,
+    retry: Option<RetryPolicy>,
+    oversized: Option<OversizedPolicy>,
+    validation: Option<ValidationPolicy>,
+    #[cfg(not(target_arch = "wasm32"))]
+    limiter: Option<RateLimiter>,
+    #[cfg(not(target_arch = "wasm32"))]
+    dedup: Option<Deduplicator>,
 }
 
 impl Async {
@@ -42,30 +65,331 @@ impl Async {
 
         Ok(Self {
             client: client.build()?,
+            mode: builder.mode,
+            retry: builder.retry,
+            oversized: builder.oversized,
+            validation: builder.validation,
+            #[cfg(not(target_arch = "wasm32"))]
+            limiter: RateLimiter::new(builder.rate_limit, builder.topic_rate_limit),
+            #[cfg(not(target_arch = "wasm32"))]
+            dedup: builder.dedup.map(Deduplicator::new),
         })
     }
 
     /// Send payload to ntfy server
-    pub(crate) async fn send(&self, url: &Url, payload: &Payload) -> Result<(), Error> {
+    pub(crate) async fn send(
+        &self,
+        url: &Url,
+        payload: &Payload,
+    ) -> Result<PublishedMessage, Error> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(dedup) = &self.dedup {
+            return self.send_dedup(dedup, url, payload).await;
+        }
+
+        let text: String = self.publish(url, payload).await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Send payload to ntfy server, discarding the response
+    pub(crate) async fn send_and_forget(&self, url: &Url, payload: &Payload) -> Result<(), Error> {
+        // The published message is returned for the suppressed duplicates
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.dedup.is_some() {
+            self.send(url, payload).await?;
+            return Ok(());
+        }
+
+        self.publish(url, payload).await?;
+        Ok(())
+    }
+
+    /// Send payload, unless duplicated within the window
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn send_dedup(
+        &self,
+        dedup: &Deduplicator,
+        url: &Url,
+        payload: &Payload,
+    ) -> Result<PublishedMessage, Error> {
+        let now: Instant = Instant::now();
+
+        // Summaries must not fail the send: the failed ones are sent again later
+        for summary in dedup.take_summaries(Some(now)) {
+            if self.publish(url, &summary).await.is_err() {
+                dedup.unsent(summary);
+            }
+        }
+
+        loop {
+            match dedup.reserve(payload, now) {
+                Reservation::Duplicate(message) => return Ok(*message),
+                Reservation::InFlight(slot) => {
+                    // If the first send failed, try again
+                    if let Some(message) = slot.wait_async().await {
+                        return Ok(message);
+                    }
+                }
+                Reservation::Reserved(reserved) => {
+                    let text: String = self.publish(url, payload).await?;
+                    let message: PublishedMessage = serde_json::from_str(&text)?;
+                    reserved.record(&message, now);
+                    return Ok(message);
+                }
+            }
+        }
+    }
+
+    /// Send the summaries of all the dedup windows
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) async fn flush_suppressed(&self, url: &Url) -> Result<(), Error> {
+        if let Some(dedup) = &self.dedup {
+            let mut summaries = dedup.take_summaries(None).into_iter();
+            while let Some(summary) = summaries.next() {
+                if let Err(e) = self.publish(url, &summary).await {
+                    dedup.unsent(summary);
+                    summaries.for_each(|summary| dedup.unsent(summary));
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the payloads, with at most `concurrency` requests in flight
+    ///
+    /// The results are returned in the same order of the payloads.
+    pub(crate) async fn send_many(
+        &self,
+        url: &Url,
+        payloads: &[&Payload],
+        concurrency: usize,
+    ) -> Vec<Result<PublishedMessage, Error>> {
+        stream::iter(payloads.iter())
+            .map(|payload| self.send(url, payload))
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Upload attachment to ntfy server
+    pub(crate) async fn upload<B>(
+        &self,
+        url: &Url,
+        payload: &Payload,
+        body: B,
+    ) -> Result<PublishedMessage, Error>
+    where
+        B: Into<Body>,
+    {
+        request::validate(self.validation.as_ref(), payload, Encoding::Headers)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.throttle(&payload.topic).await;
+        let text: String = self.try_upload(url, payload, body).await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Upload attachment, returning the response text
+    async fn try_upload<B>(&self, url: &Url, payload: &Payload, body: B) -> Result<String, Error>
+    where
+        B: Into<Body>,
+    {
+        let url: Url = request::endpoint_url(url, &[&payload.topic])?;
+
+        // Metadata are sent as headers, since the body is the attachment
+        let headers: HeaderMap = request::upload_headers(payload)?;
+
         // Build request
-        let mut builder = self.client.post(url.as_str());
+        let builder = self.client.put(url).headers(headers).body(body);
+
+        // Send request
+        let res: Response = builder.send().await?;
+        read_response(res).await
+    }
 
-        // Add payload
-        builder = builder.json(payload);
+    /// Mark the notification as read and dismiss it
+    pub(crate) async fn clear(
+        &self,
+        url: &Url,
+        topic: &str,
+        sequence_id: &str,
+    ) -> Result<(), Error> {
+        let url: Url = request::endpoint_url(url, &[topic, sequence_id, "clear"])?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.throttle(topic).await;
+        self.retry(|| async {
+            let res: Response = self.client.put(url.clone()).send().await?;
+            check_response(res).await
+        })
+        .await
+    }
+
+    /// Delete the notification
+    pub(crate) async fn delete(
+        &self,
+        url: &Url,
+        topic: &str,
+        sequence_id: &str,
+    ) -> Result<(), Error> {
+        let url: Url = request::endpoint_url(url, &[topic, sequence_id])?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.throttle(topic).await;
+        self.retry(|| async {
+            let res: Response = self.client.delete(url.clone()).send().await?;
+            check_response(res).await
+        })
+        .await
+    }
+
+    /// Check the payload as it's going to be published
+    #[cfg(all(feature = "outbox", not(target_arch = "wasm32")))]
+    #[inline]
+    pub(crate) fn validate(&self, payload: &Payload) -> Result<(), Error> {
+        self.prepare(payload).map(|_| ())
+    }
+
+    /// Check the payload, split by the [`OversizedPolicy`] if needed
+    #[inline]
+    fn prepare<'a>(&self, payload: &'a Payload) -> Result<Prepared<'a>, Error> {
+        request::prepare(
+            self.validation.as_ref(),
+            self.oversized.as_ref(),
+            self.mode,
+            payload,
+        )
+    }
+
+    /// Publish payload, retrying according to the [`RetryPolicy`]
+    #[inline]
+    async fn publish(&self, url: &Url, payload: &Payload) -> Result<String, Error> {
+        let prepared: Prepared = self.prepare(payload)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.throttle(&payload.topic).await;
+        match prepared {
+            Prepared::Payload(payload) => self.retry(|| self.try_publish(url, payload)).await,
+            Prepared::Upload(payload, body) => {
+                self.retry(|| self.try_upload(url, &payload, body.clone()))
+                    .await
+            }
+        }
+    }
+
+    /// Wait for the rate limiter, if set
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn throttle(&self, topic: &str) {
+        if let Some(limiter) = &self.limiter {
+            let wait: Duration = limiter.reserve(topic, Instant::now());
+            if !wait.is_zero() {
+                Delay::new(wait).await;
+            }
+        }
+    }
+
+    /// Send the request, retrying according to the [`RetryPolicy`]
+    async fn retry<T, F, Fut>(&self, request: F) -> Result<T, Error>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt: u32 = 0;
+        loop {
+            match request().await {
+                Ok(res) => return Ok(res),
+                Err(e) => match &self.retry {
+                    Some(policy) if policy.should_retry(attempt, &e) => {
+                        Delay::new(policy.backoff_for(attempt, &e)).await;
+                        attempt += 1;
+                    }
+                    _ => return Err(e),
+                },
+            }
+        }
+    }
+
+    async fn try_publish(&self, url: &Url, payload: &Payload) -> Result<String, Error> {
+        // Build request
+        let builder = match self.mode {
+            PublishMode::Json => self
+                .client
+                .post(url.as_str())
+                .headers(request::header_only(payload)?)
+                .json(&request::json_body(payload)?),
+            PublishMode::Headers => {
+                let url: Url = request::endpoint_url(url, &[&payload.topic])?;
+                let headers: HeaderMap = request::payload_headers(payload)?;
+                let body: String = payload.message.clone().unwrap_or_default();
+                self.client.post(url).headers(headers).body(body)
+            }
+        };
 
         // Send request
         let res: Response = builder.send().await?;
-        let res: Response = res.error_for_status()?;
+        read_response(res).await
+    }
+}
+
+/// Get full response text, checking the status
+async fn read_response(res: Response) -> Result<String, Error> {
+    let status = res.status();
+    let retry_after: Option<Duration> = request::retry_after(res.headers().get("retry-after"));
+
+    // Get full response text
+    let text: String = res.text().await?;
+
+    if !status.is_success() {
+        return Err(Error::server(status.as_u16(), text.as_bytes()).with_retry_after(retry_after));
+    }
+
+    if text.is_empty() {
+        return Err(Error::EmptyResponse);
+    }
+
+    Ok(text)
+}
 
-        // Get full response text
+/// Check the response status, discarding the body
+async fn check_response(res: Response) -> Result<(), Error> {
+    let status = res.status();
+
+    if !status.is_success() {
+        let retry_after: Option<Duration> = request::retry_after(res.headers().get("retry-after"));
         let text: String = res.text().await?;
+        return Err(Error::server(status.as_u16(), text.as_bytes()).with_retry_after(retry_after));
+    }
 
-        if text.is_empty() {
-            return Err(Error::EmptyResponse);
-        }
+    Ok(())
+}
 
-        // TODO: check the text?
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
 
-        Ok(())
+    use crate::dispatcher::testing::TestServer;
+    use crate::dispatcher::{self, Dispatcher};
+    use crate::payload::Payload;
+
+    #[tokio::test]
+    async fn test_send_many() {
+        // The even messages are slower, to complete out of order
+        let server = TestServer::start(|message| match message.parse::<u64>() {
+            Ok(i) if i % 2 == 0 => Duration::from_millis(60),
+            _ => Duration::from_millis(10),
+        });
+        let dispatcher: Dispatcher<super::Async> =
+            dispatcher::builder(&server.url).build_async().unwrap();
+
+        let payloads: Vec<Payload> = (0..8)
+            .map(|i| Payload::new("mytopic").message(i.to_string()))
+            .collect();
+        let ids: Vec<String> = dispatcher
+            .send_many(&payloads, 3)
+            .await
+            .into_iter()
+            .map(|res| res.unwrap().id)
+            .collect();
+
+        assert_eq!(ids, (0..8).map(|i| i.to_string()).collect::<Vec<_>>());
+        assert!(server.max_in_flight() > 1);
+        assert!(server.max_in_flight() <= 3);
     }
 }
@@ -1,6 +1,13 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use std::io::Read;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use http::HeaderMap;
 #[cfg(all(feature = "socks", not(target_arch = "wasm32")))]
 use ureq::Proxy;
 use ureq::config::ConfigBuilder;
@@ -10,10 +17,16 @@ use ureq::typestate::AgentScope;
 use ureq::{Agent, Body, SendBody};
 use url::Url;
 
-use super::builder::DispatcherBuilder;
+use super::builder::{DispatcherBuilder, PublishMode};
+use super::dedup::{Deduplicator, Reservation};
+use super::limiter::RateLimiter;
+use super::oversized::OversizedPolicy;
+use super::request::{self, Prepared};
+use super::retry::RetryPolicy;
 use crate::auth::Auth;
 use crate::error::Error;
-use crate::payload::Payload;
+use crate::payload::validation::Encoding;
+use crate::payload::{Payload, PublishedMessage, ValidationPolicy};
 
 struct AuthMiddleware {
     header: HeaderValue,
@@ -43,6 +56,12 @@ impl Middleware for AuthMiddleware {
 #[derive(Debug, Clone)]
 pub struct Blocking {
     client: Agent,
+    mode: PublishMode,
+    retry: Option<RetryPolicy>,
+    oversized: Option<OversizedPolicy>,
+    validation: Option<ValidationPolicy>,
+    limiter: Option<RateLimiter>,
+    dedup: Option<Deduplicator>,
 }
 
 impl Blocking {
@@ -55,6 +74,9 @@ impl Blocking {
         builder: DispatcherBuilder,
         mut client: ConfigBuilder<AgentScope>,
     ) -> Result<Self, Error> {
+        // Error responses are handled by the dispatcher, to parse the body
+        client = client.http_status_as_error(false);
+
         if let Some(auth) = builder.auth {
             // Construct middleware
             let middleware = AuthMiddleware::new(auth)?;
@@ -71,25 +93,343 @@ impl Blocking {
 
         Ok(Self {
             client: client.build().into(),
+            mode: builder.mode,
+            retry: builder.retry,
+            oversized: builder.oversized,
+            validation: builder.validation,
+            limiter: RateLimiter::new(builder.rate_limit, builder.topic_rate_limit),
+            dedup: builder.dedup.map(Deduplicator::new),
         })
     }
 
-    pub(crate) fn send(&self, url: &Url, payload: &Payload) -> Result<(), Error> {
-        // Build request
-        let builder = self.client.post(url.as_str());
+    pub(crate) fn send(&self, url: &Url, payload: &Payload) -> Result<PublishedMessage, Error> {
+        if let Some(dedup) = &self.dedup {
+            return self.send_dedup(dedup, url, payload);
+        }
+
+        let text: String = self.publish(url, payload)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub(crate) fn send_and_forget(&self, url: &Url, payload: &Payload) -> Result<(), Error> {
+        // The published message is returned for the suppressed duplicates
+        if self.dedup.is_some() {
+            self.send(url, payload)?;
+            return Ok(());
+        }
 
-        // Send request
-        let res: Response<Body> = builder.send_json(payload)?;
+        self.publish(url, payload)?;
+        Ok(())
+    }
 
-        // Get full response text
-        let text = res.into_body().read_to_string()?;
+    /// Send payload, unless duplicated within the window
+    fn send_dedup(
+        &self,
+        dedup: &Deduplicator,
+        url: &Url,
+        payload: &Payload,
+    ) -> Result<PublishedMessage, Error> {
+        let now: Instant = Instant::now();
 
-        if text.is_empty() {
-            return Err(Error::EmptyResponse);
+        // Summaries must not fail the send: the failed ones are sent again later
+        for summary in dedup.take_summaries(Some(now)) {
+            if self.publish(url, &summary).is_err() {
+                dedup.unsent(summary);
+            }
         }
 
-        // TODO: check the text?
+        loop {
+            match dedup.reserve(payload, now) {
+                Reservation::Duplicate(message) => return Ok(*message),
+                Reservation::InFlight(slot) => {
+                    // If the first send failed, try again
+                    if let Some(message) = slot.wait() {
+                        return Ok(message);
+                    }
+                }
+                Reservation::Reserved(reserved) => {
+                    let text: String = self.publish(url, payload)?;
+                    let message: PublishedMessage = serde_json::from_str(&text)?;
+                    reserved.record(&message, now);
+                    return Ok(message);
+                }
+            }
+        }
+    }
 
+    /// Send the summaries of all the dedup windows
+    pub(crate) fn flush_suppressed(&self, url: &Url) -> Result<(), Error> {
+        if let Some(dedup) = &self.dedup {
+            let mut summaries = dedup.take_summaries(None).into_iter();
+            while let Some(summary) = summaries.next() {
+                if let Err(e) = self.publish(url, &summary) {
+                    dedup.unsent(summary);
+                    summaries.for_each(|summary| dedup.unsent(summary));
+                    return Err(e);
+                }
+            }
+        }
         Ok(())
     }
+
+    /// Send the payloads using a pool of `threads` workers, sharing the same agent
+    ///
+    /// The results are returned in the same order of the payloads.
+    pub(crate) fn send_many(
+        &self,
+        url: &Url,
+        payloads: &[&Payload],
+        threads: usize,
+    ) -> Vec<Result<PublishedMessage, Error>> {
+        let threads: usize = threads.clamp(1, payloads.len().max(1));
+        let next: AtomicUsize = AtomicUsize::new(0);
+
+        // One slot per payload: the ones left empty were claimed by a worker that panicked
+        let slots: Vec<OnceLock<Result<PublishedMessage, Error>>> =
+            payloads.iter().map(|_| OnceLock::new()).collect();
+
+        thread::scope(|s| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        loop {
+                            // Take the next payload of the queue
+                            let index: usize = next.fetch_add(1, Ordering::Relaxed);
+                            match payloads.get(index) {
+                                Some(payload) => {
+                                    let _ = slots[index].set(self.send(url, payload));
+                                }
+                                None => return,
+                            }
+                        }
+                    })
+                })
+                .collect();
+
+            // Join all the workers, so the scope doesn't propagate the panics
+            for worker in workers.into_iter() {
+                let _ = worker.join();
+            }
+        });
+
+        slots
+            .into_iter()
+            .map(|slot| slot.into_inner().unwrap_or(Err(Error::WorkerPanicked)))
+            .collect()
+    }
+
+    pub(crate) fn upload<R>(
+        &self,
+        url: &Url,
+        payload: &Payload,
+        reader: R,
+    ) -> Result<PublishedMessage, Error>
+    where
+        R: Read,
+    {
+        request::validate(self.validation.as_ref(), payload, Encoding::Headers)?;
+        self.throttle(&payload.topic);
+        let text: String = self.try_upload(url, payload, reader)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    /// Upload attachment, returning the response text
+    fn try_upload<R>(&self, url: &Url, payload: &Payload, mut reader: R) -> Result<String, Error>
+    where
+        R: Read,
+    {
+        let url: Url = request::endpoint_url(url, &[&payload.topic])?;
+
+        // Metadata are sent as headers, since the body is the attachment
+        let headers: HeaderMap = request::upload_headers(payload)?;
+
+        // Build request
+        let mut builder = self.client.put(url.as_str());
+        for (name, value) in headers.iter() {
+            builder = builder.header(name, value);
+        }
+
+        // Send request, streaming the reader
+        let res: Response<Body> = builder.send(SendBody::from_reader(&mut reader))?;
+        read_response(res)
+    }
+
+    /// Mark the notification as read and dismiss it
+    pub(crate) fn clear(&self, url: &Url, topic: &str, sequence_id: &str) -> Result<(), Error> {
+        let url: Url = request::endpoint_url(url, &[topic, sequence_id, "clear"])?;
+        self.throttle(topic);
+        self.retry(|| {
+            let res: Response<Body> = self.client.put(url.as_str()).send_empty()?;
+            check_response(res)
+        })
+    }
+
+    /// Delete the notification
+    pub(crate) fn delete(&self, url: &Url, topic: &str, sequence_id: &str) -> Result<(), Error> {
+        let url: Url = request::endpoint_url(url, &[topic, sequence_id])?;
+        self.throttle(topic);
+        self.retry(|| {
+            let res: Response<Body> = self.client.delete(url.as_str()).call()?;
+            check_response(res)
+        })
+    }
+
+    /// Check the payload as it's going to be published
+    #[cfg(all(feature = "outbox", not(target_arch = "wasm32")))]
+    #[inline]
+    pub(crate) fn validate(&self, payload: &Payload) -> Result<(), Error> {
+        self.prepare(payload).map(|_| ())
+    }
+
+    /// Check the payload, split by the [`OversizedPolicy`] if needed
+    #[inline]
+    fn prepare<'a>(&self, payload: &'a Payload) -> Result<Prepared<'a>, Error> {
+        request::prepare(
+            self.validation.as_ref(),
+            self.oversized.as_ref(),
+            self.mode,
+            payload,
+        )
+    }
+
+    /// Publish payload, retrying according to the [`RetryPolicy`]
+    #[inline]
+    fn publish(&self, url: &Url, payload: &Payload) -> Result<String, Error> {
+        let prepared: Prepared = self.prepare(payload)?;
+        self.throttle(&payload.topic);
+        match prepared {
+            Prepared::Payload(payload) => self.retry(|| self.try_publish(url, payload)),
+            Prepared::Upload(payload, body) => {
+                self.retry(|| self.try_upload(url, &payload, body.as_bytes()))
+            }
+        }
+    }
+
+    /// Wait for the rate limiter, if set
+    fn throttle(&self, topic: &str) {
+        if let Some(limiter) = &self.limiter {
+            let wait: Duration = limiter.reserve(topic, Instant::now());
+            if !wait.is_zero() {
+                thread::sleep(wait);
+            }
+        }
+    }
+
+    /// Send the request, retrying according to the [`RetryPolicy`]
+    fn retry<T, F>(&self, request: F) -> Result<T, Error>
+    where
+        F: Fn() -> Result<T, Error>,
+    {
+        let mut attempt: u32 = 0;
+        loop {
+            match request() {
+                Ok(res) => return Ok(res),
+                Err(e) => match &self.retry {
+                    Some(policy) if policy.should_retry(attempt, &e) => {
+                        thread::sleep(policy.backoff_for(attempt, &e));
+                        attempt += 1;
+                    }
+                    _ => return Err(e),
+                },
+            }
+        }
+    }
+
+    fn try_publish(&self, url: &Url, payload: &Payload) -> Result<String, Error> {
+        let res: Response<Body> = match self.mode {
+            PublishMode::Json => {
+                let headers: HeaderMap = request::header_only(payload)?;
+
+                // Build request
+                let mut builder = self.client.post(url.as_str());
+                for (name, value) in headers.iter() {
+                    builder = builder.header(name, value);
+                }
+
+                // Send request
+                builder.send_json(request::json_body(payload)?)?
+            }
+            PublishMode::Headers => {
+                let url: Url = request::endpoint_url(url, &[&payload.topic])?;
+                let headers: HeaderMap = request::payload_headers(payload)?;
+
+                // Build request
+                let mut builder = self.client.post(url.as_str());
+                for (name, value) in headers.iter() {
+                    builder = builder.header(name, value);
+                }
+
+                // Send request, with the message as body
+                let body: &str = payload.message.as_deref().unwrap_or_default();
+                builder.send(body)?
+            }
+        };
+
+        read_response(res)
+    }
+}
+
+/// Get full response text, checking the status
+fn read_response(res: Response<Body>) -> Result<String, Error> {
+    let status = res.status();
+    let retry_after: Option<Duration> = request::retry_after(res.headers().get("retry-after"));
+
+    // Get full response text
+    let text: String = res.into_body().read_to_string()?;
+
+    if !status.is_success() {
+        return Err(Error::server(status.as_u16(), text.as_bytes()).with_retry_after(retry_after));
+    }
+
+    if text.is_empty() {
+        return Err(Error::EmptyResponse);
+    }
+
+    Ok(text)
+}
+
+/// Check the response status, discarding the body
+fn check_response(res: Response<Body>) -> Result<(), Error> {
+    let status = res.status();
+
+    if !status.is_success() {
+        let retry_after: Option<Duration> = request::retry_after(res.headers().get("retry-after"));
+        let text: String = res.into_body().read_to_string()?;
+        return Err(Error::server(status.as_u16(), text.as_bytes()).with_retry_after(retry_after));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::dispatcher::testing::TestServer;
+    use crate::dispatcher::{self, Dispatcher};
+    use crate::payload::Payload;
+
+    #[test]
+    fn test_send_many() {
+        // The even messages are slower, to complete out of order
+        let server = TestServer::start(|message| match message.parse::<u64>() {
+            Ok(i) if i % 2 == 0 => Duration::from_millis(60),
+            _ => Duration::from_millis(10),
+        });
+        let dispatcher: Dispatcher<super::Blocking> =
+            dispatcher::builder(&server.url).build_blocking().unwrap();
+
+        let payloads: Vec<Payload> = (0..8)
+            .map(|i| Payload::new("mytopic").message(i.to_string()))
+            .collect();
+        let ids: Vec<String> = dispatcher
+            .send_many(&payloads, 3)
+            .into_iter()
+            .map(|res| res.unwrap().id)
+            .collect();
+
+        assert_eq!(ids, (0..8).map(|i| i.to_string()).collect::<Vec<_>>());
+        assert!(server.max_in_flight() > 1);
+        assert!(server.max_in_flight() <= 3);
+    }
 }
@@ -14,14 +14,56 @@ use url::Url;
 use super::Async;
 #[cfg(feature = "blocking-dispatcher")]
 use super::Blocking;
+#[cfg(not(target_arch = "wasm32"))]
+use super::dedup::DedupPolicy;
+#[cfg(not(target_arch = "wasm32"))]
+use super::limiter::RateLimit;
+use super::oversized::OversizedPolicy;
+use super::retry::RetryPolicy;
+use super::transport::PublishTransport;
 #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
 use super::{Dispatcher, Error};
 use crate::auth::Auth;
+use crate::payload::ValidationPolicy;
+use crate::payload::validation::Encoding;
+
+/// How the payload is published to the server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PublishMode {
+    /// POST the payload as JSON to the server root
+    ///
+    /// <https://docs.ntfy.sh/publish/#publish-as-json>
+    #[default]
+    Json,
+    /// POST the message as body to `/<topic>`, with the other fields as `X-*` headers
+    ///
+    /// <https://docs.ntfy.sh/publish/#list-of-all-parameters>
+    Headers,
+}
+
+impl From<PublishMode> for Encoding {
+    fn from(mode: PublishMode) -> Self {
+        match mode {
+            PublishMode::Json => Self::Json,
+            PublishMode::Headers => Self::Headers,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct DispatcherBuilder {
     url: String,
     pub(crate) auth: Option<Auth>,
+    pub(crate) mode: PublishMode,
+    pub(crate) retry: Option<RetryPolicy>,
+    pub(crate) oversized: Option<OversizedPolicy>,
+    pub(crate) validation: Option<ValidationPolicy>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) rate_limit: Option<RateLimit>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) topic_rate_limit: Option<RateLimit>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) dedup: Option<DedupPolicy>,
     #[cfg(all(feature = "socks", not(target_arch = "wasm32")))]
     pub(crate) proxy: Option<String>,
 }
@@ -35,6 +77,16 @@ impl DispatcherBuilder {
         Self {
             url: url.into(),
             auth: None,
+            mode: PublishMode::default(),
+            retry: None,
+            oversized: None,
+            validation: Some(ValidationPolicy::default()),
+            #[cfg(not(target_arch = "wasm32"))]
+            rate_limit: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            topic_rate_limit: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            dedup: None,
             #[cfg(all(feature = "socks", not(target_arch = "wasm32")))]
             proxy: None,
         }
@@ -46,6 +98,79 @@ impl DispatcherBuilder {
         self
     }
 
+    /// Set publish mode (default: [`PublishMode::Json`])
+    #[inline]
+    pub fn publish_mode(mut self, mode: PublishMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Retry failed requests according to the [`RetryPolicy`] (default: no retries)
+    #[inline]
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Upload the oversized messages as text attachments, according to the [`OversizedPolicy`] (default: disabled)
+    ///
+    /// Without it, in [`PublishMode::Json`] the messages longer than [`MAX_MESSAGE_LEN`](crate::payload::MAX_MESSAGE_LEN)
+    /// are rejected by the [`ValidationPolicy`].
+    #[inline]
+    pub fn oversized_policy(mut self, policy: OversizedPolicy) -> Self {
+        self.oversized = Some(policy);
+        self
+    }
+
+    /// Check the payloads before sending according to the [`ValidationPolicy`] (default: [`ValidationPolicy::default`])
+    ///
+    /// Only the rules of the fields sent as headers in the [`PublishMode`] are checked.
+    #[inline]
+    pub fn validation_policy(mut self, policy: ValidationPolicy) -> Self {
+        self.validation = Some(policy);
+        self
+    }
+
+    /// Send the payloads without checking them, leaving it to the server
+    #[inline]
+    pub fn skip_validation(mut self) -> Self {
+        self.validation = None;
+        self
+    }
+
+    /// Limit the sends of all the topics (default: no limit)
+    ///
+    /// Requests exceeding the limit (sends, clears and deletes) are delayed, not rejected.
+    /// The limiter is shared by the clones of the dispatcher.
+    #[inline]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Limit the sends of every topic, each one with its own bucket (default: no limit)
+    ///
+    /// Requests exceeding the limit (sends, clears and deletes) are delayed, not rejected.
+    /// The limiter is shared by the clones of the dispatcher.
+    #[inline]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn topic_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.topic_rate_limit = Some(limit);
+        self
+    }
+
+    /// Suppress the duplicated payloads according to the [`DedupPolicy`] (default: disabled)
+    ///
+    /// The windows are shared by the clones of the dispatcher.
+    /// The summaries aren't sent by a timer: see the [limitations](DedupPolicy#limitations).
+    #[inline]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn dedup_policy(mut self, policy: DedupPolicy) -> Self {
+        self.dedup = Some(policy);
+        self
+    }
+
     #[inline]
     #[cfg(all(feature = "socks", not(target_arch = "wasm32")))]
     pub fn proxy<S>(mut self, proxy: S) -> Self
@@ -70,6 +195,7 @@ impl DispatcherBuilder {
         Ok(Dispatcher {
             url: Url::parse(&self.url)?,
             inner: Async::new(self)?,
+            validation: None,
         })
     }
 
@@ -81,6 +207,7 @@ impl DispatcherBuilder {
         Ok(Dispatcher {
             url: Url::parse(&self.url)?,
             inner: Async::new_with_client(self, client)?,
+            validation: None,
         })
     }
 
@@ -89,6 +216,7 @@ impl DispatcherBuilder {
         Ok(Dispatcher {
             url: Url::parse(&self.url)?,
             inner: Blocking::new(self)?,
+            validation: None,
         })
     }
 
@@ -100,6 +228,57 @@ impl DispatcherBuilder {
         Ok(Dispatcher {
             url: Url::parse(&self.url)?,
             inner: Blocking::new_with_client(self, client)?,
+            validation: None,
+        })
+    }
+
+    /// Build dispatcher with a custom transport (i.e. [`Memory`](crate::memory::Memory) for tests)
+    ///
+    /// The payloads are checked by the dispatcher, according to the [`ValidationPolicy`] and the [`PublishMode`].
+    /// The other options depend on the client, so they are up to the transport: returns
+    /// [`Error::UnsupportedByTransport`] if the credentials, the proxy or any other policy is set.
+    ///
+    /// The dispatcher has only the methods backed by [`PublishTransport`]: `send`, `send_and_forget`,
+    /// `clear`, `delete` and `cancel_scheduled`. Uploads, `send_many` and `flush_suppressed`
+    /// are available only with the built-in transports.
+    pub fn build_with_transport<T>(self, transport: T) -> Result<Dispatcher<T>, Error>
+    where
+        T: PublishTransport,
+    {
+        if self.auth.is_some() {
+            return Err(Error::UnsupportedByTransport("credentials"));
+        }
+        if self.retry.is_some() {
+            return Err(Error::UnsupportedByTransport("retry_policy"));
+        }
+        if self.oversized.is_some() {
+            return Err(Error::UnsupportedByTransport("oversized_policy"));
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.rate_limit.is_some() {
+            return Err(Error::UnsupportedByTransport("rate_limit"));
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.topic_rate_limit.is_some() {
+            return Err(Error::UnsupportedByTransport("topic_rate_limit"));
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.dedup.is_some() {
+            return Err(Error::UnsupportedByTransport("dedup_policy"));
+        }
+
+        #[cfg(all(feature = "socks", not(target_arch = "wasm32")))]
+        if self.proxy.is_some() {
+            return Err(Error::UnsupportedByTransport("proxy"));
+        }
+
+        Ok(Dispatcher {
+            url: Url::parse(&self.url)?,
+            inner: transport,
+            validation: self.validation.map(|policy| (policy, self.mode)),
         })
     }
 }
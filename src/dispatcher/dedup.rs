@@ -0,0 +1,391 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::HashMap;
+#[cfg(feature = "async-dispatcher")]
+use std::future::Future;
+#[cfg(feature = "async-dispatcher")]
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+#[cfg(feature = "async-dispatcher")]
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::payload::{Payload, Priority, PublishedMessage};
+
+/// Suppress the duplicated payloads sent within a window
+///
+/// Payloads are considered duplicated if they have the same topic, title, message and tags.
+/// A suppressed send returns the message published for the first payload of the window,
+/// waiting for it if still in flight.
+///
+/// By default, the window is 60 secs and the summary is enabled.
+///
+/// # Limitations
+///
+/// There's no background timer: the summary with the number of suppressed payloads is sent along with
+/// the next payload after the window closes, or by `flush_suppressed`. So a burst followed by silence
+/// isn't reported until something else is sent: call `flush_suppressed` periodically, or before shutting down.
+/// Summaries failing to be sent are kept and sent again at the next attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DedupPolicy {
+    window: Duration,
+    summary: bool,
+}
+
+impl Default for DedupPolicy {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            summary: true,
+        }
+    }
+}
+
+impl DedupPolicy {
+    /// New default dedup policy
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time after the first payload in which the duplicates are suppressed (default: 60 secs)
+    #[inline]
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Send a "N similar notifications suppressed" summary when the window closes (default: true)
+    #[inline]
+    pub fn summary(mut self, summary: bool) -> Self {
+        self.summary = summary;
+        self
+    }
+}
+
+/// Result of the first send of a window, awaited by the duplicates sent meanwhile
+#[derive(Debug, Default)]
+pub(crate) struct Slot {
+    state: Mutex<SlotState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct SlotState {
+    /// `Some(None)` if the send failed
+    result: Option<Option<PublishedMessage>>,
+    #[cfg(feature = "async-dispatcher")]
+    wakers: Vec<Waker>,
+}
+
+impl Slot {
+    fn state(&self) -> MutexGuard<'_, SlotState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn resolve(&self, message: Option<PublishedMessage>) {
+        let mut state = self.state();
+        state.result = Some(message);
+        #[cfg(feature = "async-dispatcher")]
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+        self.condvar.notify_all();
+    }
+
+    /// Wait for the first send, returning `None` if it failed
+    #[cfg(feature = "blocking-dispatcher")]
+    pub(crate) fn wait(&self) -> Option<PublishedMessage> {
+        let state = self
+            .condvar
+            .wait_while(self.state(), |state| state.result.is_none())
+            .unwrap_or_else(|e| e.into_inner());
+        state.result.clone().flatten()
+    }
+
+    /// Wait for the first send, returning `None` if it failed
+    #[cfg(feature = "async-dispatcher")]
+    pub(crate) fn wait_async(self: Arc<Self>) -> SlotWait {
+        SlotWait { slot: self }
+    }
+}
+
+/// Future of [`Slot::wait_async`]
+#[cfg(feature = "async-dispatcher")]
+pub(crate) struct SlotWait {
+    slot: Arc<Slot>,
+}
+
+#[cfg(feature = "async-dispatcher")]
+impl Future for SlotWait {
+    type Output = Option<PublishedMessage>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.slot.state();
+        match &state.result {
+            Some(result) => Poll::Ready(result.clone()),
+            None => {
+                state.wakers.push(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Outcome of [`Deduplicator::reserve`]
+pub(crate) enum Reservation<'a> {
+    /// Suppressed: the message published for the first payload of the window
+    Duplicate(Box<PublishedMessage>),
+    /// Suppressed, but the first payload of the window is still in flight
+    InFlight(Arc<Slot>),
+    /// First payload of the window: to be published and recorded
+    Reserved(Reserved<'a>),
+}
+
+/// Window reserved for a payload being published
+///
+/// Released if dropped without being recorded (i.e. failed or cancelled send),
+/// so the waiting duplicates try again.
+pub(crate) struct Reserved<'a> {
+    dedup: &'a Deduplicator,
+    fingerprint: Fingerprint,
+    slot: Arc<Slot>,
+    recorded: bool,
+}
+
+impl Reserved<'_> {
+    /// Open the window for the published payload
+    pub(crate) fn record(mut self, message: &PublishedMessage, now: Instant) {
+        if let Some(window) = self.dedup.state().windows.get_mut(&self.fingerprint) {
+            window.until = now + self.dedup.policy.window;
+            window.message = Some(message.clone());
+        }
+
+        self.recorded = true;
+        self.slot.resolve(Some(message.clone()));
+    }
+}
+
+impl Drop for Reserved<'_> {
+    fn drop(&mut self) {
+        if !self.recorded {
+            self.dedup.state().windows.remove(&self.fingerprint);
+            self.slot.resolve(None);
+        }
+    }
+}
+
+/// Fields of the payload compared to find the duplicates
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Fingerprint {
+    topic: String,
+    title: Option<String>,
+    message: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+impl Fingerprint {
+    fn new(payload: &Payload) -> Self {
+        Self {
+            topic: payload.topic.clone(),
+            title: payload.title.clone(),
+            message: payload.message.clone(),
+            tags: payload.tags.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Window {
+    until: Instant,
+    /// `None` while the first payload is in flight
+    message: Option<PublishedMessage>,
+    slot: Arc<Slot>,
+    suppressed: u32,
+    priority: Option<Priority>,
+}
+
+impl Window {
+    fn summary(&self, fingerprint: &Fingerprint) -> Payload {
+        let mut payload = Payload::new(&fingerprint.topic).message(format!(
+            "{} similar notifications suppressed",
+            self.suppressed
+        ));
+        payload.title = fingerprint.title.clone();
+        payload.tags = fingerprint.tags.clone();
+        payload.priority = self.priority;
+        payload
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    windows: HashMap<Fingerprint, Window>,
+    /// Summaries failed to be sent, to try again
+    unsent: Vec<Payload>,
+}
+
+/// Dedup windows shared by the clones of the dispatcher
+#[derive(Debug, Clone)]
+pub(crate) struct Deduplicator {
+    policy: DedupPolicy,
+    state: Arc<Mutex<State>>,
+}
+
+impl Deduplicator {
+    pub(crate) fn new(policy: DedupPolicy) -> Self {
+        Self {
+            policy,
+            state: Arc::new(Mutex::new(State::default())),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Check if the payload is a duplicate, otherwise reserve its window before publishing it
+    ///
+    /// Checked and reserved at once, so the concurrent duplicates are suppressed too.
+    pub(crate) fn reserve(&self, payload: &Payload, now: Instant) -> Reservation<'_> {
+        let mut state = self.state();
+        let state: &mut State = &mut state;
+        let fingerprint: Fingerprint = Fingerprint::new(payload);
+
+        if let Some(window) = state.windows.get_mut(&fingerprint) {
+            match &window.message {
+                Some(message) if now < window.until => {
+                    window.suppressed = window.suppressed.saturating_add(1);
+                    return Reservation::Duplicate(Box::new(message.clone()));
+                }
+                None => {
+                    window.suppressed = window.suppressed.saturating_add(1);
+                    return Reservation::InFlight(window.slot.clone());
+                }
+                // Expired, but its summary not taken yet: replaced below
+                Some(..) => {
+                    if self.policy.summary && window.suppressed > 0 {
+                        state.unsent.push(window.summary(&fingerprint));
+                    }
+                }
+            }
+        }
+
+        let slot: Arc<Slot> = Arc::new(Slot::default());
+        state.windows.insert(
+            fingerprint.clone(),
+            Window {
+                until: now + self.policy.window,
+                message: None,
+                slot: slot.clone(),
+                suppressed: 0,
+                priority: payload.priority,
+            },
+        );
+
+        Reservation::Reserved(Reserved {
+            dedup: self,
+            fingerprint,
+            slot,
+            recorded: false,
+        })
+    }
+
+    /// Close the windows expired at `now` (all of them if `None`), returning the summaries to send
+    ///
+    /// The summaries failed to be sent before are returned too.
+    /// The windows with the first payload still in flight are kept open.
+    pub(crate) fn take_summaries(&self, now: Option<Instant>) -> Vec<Payload> {
+        let mut state = self.state();
+        let state: &mut State = &mut state;
+        let mut summaries: Vec<Payload> = std::mem::take(&mut state.unsent);
+
+        state.windows.retain(|fingerprint, window| {
+            let closed: bool =
+                window.message.is_some() && now.is_none_or(|now| now >= window.until);
+            if closed && self.policy.summary && window.suppressed > 0 {
+                summaries.push(window.summary(fingerprint));
+            }
+            !closed
+        });
+
+        summaries
+    }
+
+    /// Keep the summary failed to be sent, to try again with the next payload or flush
+    pub(crate) fn unsent(&self, summary: Payload) {
+        self.state().unsent.push(summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deduplicator() {
+        let dedup = Deduplicator::new(DedupPolicy::new().window(Duration::from_secs(60)));
+        let now: Instant = Instant::now();
+
+        let payload = Payload::new("alerts").title("Health check").message("down");
+        let message: PublishedMessage = serde_json::from_str(
+            r#"{"id":"sPs71M8A2T","time":1643935928,"event":"message","topic":"alerts","message":"down"}"#,
+        )
+        .unwrap();
+
+        let Reservation::Reserved(reserved) = dedup.reserve(&payload, now) else {
+            panic!("not reserved");
+        };
+
+        // Concurrent duplicate, while the first one is in flight
+        let Reservation::InFlight(slot) = dedup.reserve(&payload, now) else {
+            panic!("not in flight");
+        };
+        reserved.record(&message, now);
+        assert_eq!(slot.state().result, Some(Some(message.clone())));
+
+        assert!(matches!(dedup.reserve(&payload, now), Reservation::Duplicate(m) if *m == message));
+        assert!(matches!(
+            dedup.reserve(&payload.clone().message("up"), now),
+            Reservation::Reserved(..)
+        ));
+        assert!(dedup.take_summaries(Some(now)).is_empty());
+
+        let later: Instant = now + Duration::from_secs(60);
+        let summaries: Vec<Payload> = dedup.take_summaries(Some(later));
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(
+            summaries[0].message.as_deref(),
+            Some("2 similar notifications suppressed")
+        );
+        assert_eq!(summaries[0].title.as_deref(), Some("Health check"));
+        assert!(dedup.take_summaries(None).is_empty());
+
+        // Failed summaries are returned again
+        dedup.unsent(summaries[0].clone());
+        assert_eq!(dedup.take_summaries(Some(later)), summaries);
+    }
+
+    #[test]
+    fn test_deduplicator_release() {
+        let dedup = Deduplicator::new(DedupPolicy::new());
+        let now: Instant = Instant::now();
+        let payload = Payload::new("alerts").message("down");
+
+        let Reservation::Reserved(reserved) = dedup.reserve(&payload, now) else {
+            panic!("not reserved");
+        };
+        let Reservation::InFlight(slot) = dedup.reserve(&payload, now) else {
+            panic!("not in flight");
+        };
+
+        // Failed send: the duplicates try again
+        drop(reserved);
+        assert_eq!(slot.state().result, Some(None));
+        assert!(matches!(
+            dedup.reserve(&payload, now),
+            Reservation::Reserved(..)
+        ));
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Token-bucket rate limit
+///
+/// Up to `burst` messages are sent immediately, then one every `interval`.
+/// The default matches the message limit of the ntfy server: burst of 60, then one every 5 secs.
+///
+/// <https://docs.ntfy.sh/config/#rate-limiting>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RateLimit {
+    burst: u32,
+    interval: Duration,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            burst: 60,
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RateLimit {
+    /// New rate limit: `burst` messages, then one every `interval`
+    #[inline]
+    pub fn new(burst: u32, interval: Duration) -> Self {
+        Self {
+            burst: burst.max(1),
+            interval,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    /// Available tokens, negative when sends are queued
+    tokens: f64,
+    last: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            last: now,
+        }
+    }
+
+    /// Check if the bucket is refilled at `now`, so it's the same as a new one
+    fn is_full(&self, now: Instant) -> bool {
+        let interval: f64 = self.limit.interval.as_secs_f64();
+        if interval <= 0.0 {
+            return true;
+        }
+        let elapsed: f64 = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens + elapsed / interval >= self.limit.burst as f64
+    }
+
+    /// Take a token, returning the time to wait for it
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let interval: f64 = self.limit.interval.as_secs_f64();
+
+        if interval > 0.0 {
+            let elapsed: f64 = now.saturating_duration_since(self.last).as_secs_f64();
+            self.tokens = (self.tokens + elapsed / interval).min(self.limit.burst as f64);
+        } else {
+            self.tokens = self.limit.burst as f64;
+        }
+        self.last = now;
+
+        self.tokens -= 1.0;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens * interval)
+        }
+    }
+}
+
+/// Min number of topic buckets before pruning the full ones
+const PRUNE_MIN: usize = 64;
+
+#[derive(Debug)]
+struct Buckets {
+    global: Option<Bucket>,
+    topic: Option<RateLimit>,
+    topics: HashMap<String, Bucket>,
+    /// Number of topic buckets triggering the next pruning
+    prune_at: usize,
+}
+
+impl Buckets {
+    /// Drop the full topic buckets, so the map doesn't grow with every topic ever used
+    ///
+    /// Done when the map doubled since the last pruning, to keep it amortized.
+    fn prune(&mut self, now: Instant) {
+        if self.topics.len() < self.prune_at {
+            return;
+        }
+        self.topics.retain(|_, bucket| !bucket.is_full(now));
+        self.prune_at = (self.topics.len() * 2).max(PRUNE_MIN);
+    }
+}
+
+/// Rate limiter shared by the clones of the dispatcher
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimiter {
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    /// Returns `None` if no limit is set
+    pub(crate) fn new(global: Option<RateLimit>, topic: Option<RateLimit>) -> Option<Self> {
+        if global.is_none() && topic.is_none() {
+            return None;
+        }
+
+        let now: Instant = Instant::now();
+        Some(Self {
+            buckets: Arc::new(Mutex::new(Buckets {
+                global: global.map(|limit| Bucket::new(limit, now)),
+                topic,
+                topics: HashMap::new(),
+                prune_at: PRUNE_MIN,
+            })),
+        })
+    }
+
+    /// Reserve a send to the topic, returning the time to wait before sending
+    pub(crate) fn reserve(&self, topic: &str, now: Instant) -> Duration {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let buckets: &mut Buckets = &mut buckets;
+
+        let global: Duration = match &mut buckets.global {
+            Some(bucket) => bucket.reserve(now),
+            None => Duration::ZERO,
+        };
+
+        let topic: Duration = match buckets.topic {
+            Some(limit) => {
+                buckets.prune(now);
+                buckets
+                    .topics
+                    .entry(topic.to_string())
+                    .or_insert_with(|| Bucket::new(limit, now))
+                    .reserve(now)
+            }
+            None => Duration::ZERO,
+        };
+
+        global.max(topic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limit = RateLimit::new(2, Duration::from_secs(5));
+        let limiter = RateLimiter::new(Some(limit), None).unwrap();
+        let now: Instant = Instant::now();
+
+        assert_eq!(limiter.reserve("a", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("b", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("a", now), Duration::from_secs(5));
+        assert_eq!(limiter.reserve("a", now), Duration::from_secs(10));
+
+        // Shared by the clones
+        let clone = limiter.clone();
+        assert_eq!(
+            clone.reserve("a", now + Duration::from_secs(10)),
+            Duration::from_secs(5)
+        );
+
+        let limiter = RateLimiter::new(None, Some(limit)).unwrap();
+        assert_eq!(limiter.reserve("a", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("a", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("b", now), Duration::ZERO);
+        assert_eq!(limiter.reserve("a", now), Duration::from_secs(5));
+
+        assert!(RateLimiter::new(None, None).is_none());
+    }
+
+    #[test]
+    fn test_rate_limiter_prune() {
+        let limit = RateLimit::new(1, Duration::from_secs(5));
+        let limiter = RateLimiter::new(None, Some(limit)).unwrap();
+        let now: Instant = Instant::now();
+
+        for i in 0..PRUNE_MIN {
+            limiter.reserve(&format!("topic-{i}"), now);
+        }
+        let topics = || limiter.buckets.lock().unwrap().topics.len();
+        assert_eq!(topics(), PRUNE_MIN);
+
+        // Not refilled yet: kept
+        assert_eq!(limiter.reserve("topic-0", now), Duration::from_secs(5));
+        assert_eq!(topics(), PRUNE_MIN);
+
+        // Next pruning when doubled
+        for i in PRUNE_MIN..PRUNE_MIN * 2 {
+            limiter.reserve(&format!("topic-{i}"), now);
+        }
+        assert_eq!(topics(), PRUNE_MIN * 2);
+
+        // Refilled: pruned, except the one being reserved
+        let later: Instant = now + Duration::from_secs(20);
+        assert_eq!(limiter.reserve("topic-1", later), Duration::ZERO);
+        assert_eq!(topics(), 1);
+        assert_eq!(limiter.reserve("topic-1", later), Duration::from_secs(5));
+    }
+}
@@ -1,6 +1,16 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+#[cfg(feature = "blocking-dispatcher")]
+use std::fs::File;
+#[cfg(feature = "blocking-dispatcher")]
+use std::io::Read;
+#[cfg(any(
+    feature = "blocking-dispatcher",
+    all(feature = "async-dispatcher", not(target_arch = "wasm32"))
+))]
+use std::path::Path;
+
 use url::Url;
 
 #[cfg(feature = "async-dispatcher")]
@@ -8,15 +18,38 @@ mod r#async;
 #[cfg(feature = "blocking-dispatcher")]
 mod blocking;
 pub mod builder;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod dedup;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod limiter;
+pub mod multi;
+#[cfg(all(feature = "outbox", not(target_arch = "wasm32")))]
+pub mod outbox;
+pub mod oversized;
+mod request;
+pub mod retry;
+#[cfg(test)]
+mod testing;
+pub mod transport;
 
 #[cfg(feature = "async-dispatcher")]
 pub use self::r#async::Async;
 #[cfg(feature = "blocking-dispatcher")]
 pub use self::blocking::Blocking;
-pub use self::builder::DispatcherBuilder;
+pub use self::builder::{DispatcherBuilder, PublishMode};
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::dedup::DedupPolicy;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::limiter::RateLimit;
+pub use self::multi::{EndpointResult, MultiDispatcher, MultiMode};
+#[cfg(all(feature = "outbox", not(target_arch = "wasm32")))]
+pub use self::outbox::{DropPolicy, Outbox, OutboxFailure, OutboxPolicy};
+pub use self::oversized::OversizedPolicy;
+pub use self::retry::RetryPolicy;
+pub use self::transport::PublishTransport;
 use crate::auth::Auth;
 use crate::error::Error;
-use crate::payload::Payload;
+use crate::payload::{Payload, PublishedMessage, ValidationPolicy};
 
 /// Creates a [`DispatcherBuilder`]
 #[inline]
@@ -34,6 +67,8 @@ where
 {
     url: Url,
     inner: T,
+    /// Checks of the custom transports: the built-in ones validate the payloads while publishing them
+    validation: Option<(ValidationPolicy, PublishMode)>,
 }
 
 impl<T> Dispatcher<T>
@@ -59,22 +94,226 @@ where
     {
         DispatcherBuilder::new(url)
     }
+
+    /// Server URL
+    #[inline]
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Check the payload according to the [`ValidationPolicy`] of a custom transport
+    fn validate(&self, payload: &Payload) -> Result<(), Error> {
+        match &self.validation {
+            Some((policy, mode)) => policy.check(payload, (*mode).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T> Dispatcher<T>
+where
+    T: PublishTransport,
+{
+    /// Send payload to ntfy server
+    ///
+    /// Returns the message published by the server.
+    #[inline]
+    pub fn send<'a>(&'a self, payload: &'a Payload) -> T::Output<'a, PublishedMessage> {
+        match self.validate(payload) {
+            Ok(()) => self.inner.send(&self.url, payload),
+            Err(e) => T::fail(e),
+        }
+    }
+
+    /// Send payload to ntfy server without parsing the published message
+    #[inline]
+    pub fn send_and_forget<'a>(&'a self, payload: &'a Payload) -> T::Output<'a, ()> {
+        match self.validate(payload) {
+            Ok(()) => self.inner.send_and_forget(&self.url, payload),
+            Err(e) => T::fail(e),
+        }
+    }
+
+    /// Mark the notification with the sequence ID as read and dismiss it
+    ///
+    /// <https://docs.ntfy.sh/publish/#updating-deleting-notifications>
+    #[inline]
+    pub fn clear<U, S>(&self, topic: U, sequence_id: S) -> T::Output<'_, ()>
+    where
+        U: Into<String>,
+        S: Into<String>,
+    {
+        self.inner
+            .clear(&self.url, topic.into(), sequence_id.into())
+    }
+
+    /// Cancel a scheduled message, using the message returned by [`Dispatcher::send`]
+    ///
+    /// <https://docs.ntfy.sh/publish/#scheduled-delivery>
+    #[inline]
+    pub fn cancel_scheduled(&self, message: &PublishedMessage) -> T::Output<'_, ()> {
+        // The sequence ID defaults to the message ID
+        let sequence_id: String = message
+            .sequence_id
+            .clone()
+            .unwrap_or_else(|| message.id.clone());
+        self.inner
+            .delete(&self.url, message.topic.clone(), sequence_id)
+    }
+
+    /// Delete the notification with the sequence ID
+    ///
+    /// <https://docs.ntfy.sh/publish/#updating-deleting-notifications>
+    #[inline]
+    pub fn delete<U, S>(&self, topic: U, sequence_id: S) -> T::Output<'_, ()>
+    where
+        U: Into<String>,
+        S: Into<String>,
+    {
+        self.inner
+            .delete(&self.url, topic.into(), sequence_id.into())
+    }
 }
 
 #[cfg(feature = "async-dispatcher")]
 impl Dispatcher<Async> {
-    /// Send payload to ntfy server
+    /// Send the summaries of the payloads suppressed by the [`DedupPolicy`], closing all the windows
+    ///
+    /// Useful before shutting down, or periodically, since the summaries are otherwise sent along with the next payload.
+    /// On error, the summaries not sent are kept for the next attempt.
     #[inline]
-    pub async fn send(&self, payload: &Payload) -> Result<(), Error> {
-        self.inner.send(&self.url, payload).await
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn flush_suppressed(&self) -> Result<(), Error> {
+        self.inner.flush_suppressed(&self.url).await
+    }
+
+    /// Send many payloads concurrently, with at most `concurrency` requests in flight
+    ///
+    /// The connection pool of the client is shared by the requests.
+    /// Returns the result of every payload, in the same order.
+    pub async fn send_many<'a, I>(
+        &self,
+        payloads: I,
+        concurrency: usize,
+    ) -> Vec<Result<PublishedMessage, Error>>
+    where
+        I: IntoIterator<Item = &'a Payload>,
+    {
+        let payloads: Vec<&Payload> = payloads.into_iter().collect();
+        self.inner
+            .send_many(&self.url, &payloads, concurrency)
+            .await
+    }
+}
+
+#[cfg(feature = "async-dispatcher")]
+impl Dispatcher<Async> {
+    /// Upload a file attachment to ntfy server
+    ///
+    /// The body is streamed to the server, while the payload metadata
+    /// (title, tags, priority, filename, ...) are sent as `X-*` headers.
+    /// The [`Payload::attach`] URL is ignored by the server when uploading.
+    ///
+    /// Accepts anything that can be converted into a [`reqwest::Body`],
+    /// like `Vec<u8>`, `String` or a `tokio::fs::File`.
+    ///
+    /// <https://docs.ntfy.sh/publish/#attach-local-file>
+    #[inline]
+    pub async fn upload<B>(&self, payload: &Payload, body: B) -> Result<PublishedMessage, Error>
+    where
+        B: Into<reqwest::Body>,
+    {
+        self.inner.upload(&self.url, payload, body).await
+    }
+
+    /// Upload a local file as attachment to ntfy server
+    ///
+    /// The file is streamed with [`tokio::fs::File`], so a tokio runtime is required.
+    /// If [`Payload::filename`] isn't set, the name of the file is used.
+    ///
+    /// <https://docs.ntfy.sh/publish/#attach-local-file>
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn upload_file<P>(
+        &self,
+        payload: &Payload,
+        path: P,
+    ) -> Result<PublishedMessage, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path: &Path = path.as_ref();
+        let file = tokio::fs::File::open(path).await?;
+
+        match (&payload.filename, path.file_name()) {
+            (None, Some(name)) => {
+                let payload = payload.clone().filename(name.to_string_lossy());
+                self.inner.upload(&self.url, &payload, file).await
+            }
+            _ => self.inner.upload(&self.url, payload, file).await,
+        }
     }
 }
 
 #[cfg(feature = "blocking-dispatcher")]
 impl Dispatcher<Blocking> {
-    /// Send payload to ntfy server
+    /// Send the summaries of the payloads suppressed by the [`DedupPolicy`], closing all the windows
+    ///
+    /// Useful before shutting down, or periodically, since the summaries are otherwise sent along with the next payload.
+    /// On error, the summaries not sent are kept for the next attempt.
+    #[inline]
+    pub fn flush_suppressed(&self) -> Result<(), Error> {
+        self.inner.flush_suppressed(&self.url)
+    }
+
+    /// Send many payloads concurrently, using a pool of `threads` workers
+    ///
+    /// The connection pool of the agent is shared by the workers.
+    /// Returns the result of every payload, in the same order.
+    /// A payload sent by a worker that panicked gets [`Error::WorkerPanicked`].
+    pub fn send_many<'a, I>(
+        &self,
+        payloads: I,
+        threads: usize,
+    ) -> Vec<Result<PublishedMessage, Error>>
+    where
+        I: IntoIterator<Item = &'a Payload>,
+    {
+        let payloads: Vec<&Payload> = payloads.into_iter().collect();
+        self.inner.send_many(&self.url, &payloads, threads)
+    }
+
+    /// Upload a file attachment to ntfy server
+    ///
+    /// The reader is streamed to the server, while the payload metadata
+    /// (title, tags, priority, filename, ...) are sent as `X-*` headers.
+    ///
+    /// <https://docs.ntfy.sh/publish/#attach-local-file>
     #[inline]
-    pub fn send(&self, payload: &Payload) -> Result<(), Error> {
-        self.inner.send(&self.url, payload)
+    pub fn upload<R>(&self, payload: &Payload, reader: R) -> Result<PublishedMessage, Error>
+    where
+        R: Read,
+    {
+        self.inner.upload(&self.url, payload, reader)
+    }
+
+    /// Upload a local file as attachment to ntfy server
+    ///
+    /// If [`Payload::filename`] isn't set, the name of the file is used.
+    ///
+    /// <https://docs.ntfy.sh/publish/#attach-local-file>
+    pub fn upload_file<P>(&self, payload: &Payload, path: P) -> Result<PublishedMessage, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path: &Path = path.as_ref();
+        let file: File = File::open(path)?;
+
+        match (&payload.filename, path.file_name()) {
+            (None, Some(name)) => {
+                let payload = payload.clone().filename(name.to_string_lossy());
+                self.inner.upload(&self.url, &payload, file)
+            }
+            _ => self.inner.upload(&self.url, payload, file),
+        }
     }
 }
@@ -0,0 +1,382 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+#[cfg(feature = "async-dispatcher")]
+use std::future::Future;
+use std::future::{self, Ready};
+#[cfg(feature = "blocking-dispatcher")]
+use std::thread;
+
+#[cfg(feature = "async-dispatcher")]
+use futures_util::future::join_all;
+use url::Url;
+
+#[cfg(feature = "async-dispatcher")]
+use super::Async;
+#[cfg(feature = "blocking-dispatcher")]
+use super::Blocking;
+use super::Dispatcher;
+use crate::error::Error;
+use crate::memory::Memory;
+use crate::payload::{Payload, PublishedMessage};
+
+/// How the payloads are delivered to the servers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MultiMode {
+    /// Send to the servers in order, stopping at the first success
+    #[default]
+    Failover,
+    /// Send to all the servers concurrently
+    FanOut,
+}
+
+/// Result of the send to a server
+#[derive(Debug)]
+pub struct EndpointResult {
+    /// Server URL
+    pub url: Url,
+    /// Message published by the server, or the error
+    pub result: Result<PublishedMessage, Error>,
+}
+
+impl EndpointResult {
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Dispatcher sending to several ntfy servers
+///
+/// Every server has its own [`Dispatcher`], with its own credentials, proxy and policies.
+#[derive(Debug, Clone)]
+pub struct MultiDispatcher<T>
+where
+    T: Clone,
+{
+    mode: MultiMode,
+    dispatchers: Vec<Dispatcher<T>>,
+}
+
+impl<T> MultiDispatcher<T>
+where
+    T: Clone,
+{
+    /// New multi-server dispatcher, with the servers in priority order
+    #[inline]
+    pub fn new(mode: MultiMode, dispatchers: Vec<Dispatcher<T>>) -> Self {
+        Self { mode, dispatchers }
+    }
+
+    #[inline]
+    pub fn mode(&self) -> MultiMode {
+        self.mode
+    }
+
+    #[inline]
+    pub fn dispatchers(&self) -> &[Dispatcher<T>] {
+        &self.dispatchers
+    }
+}
+
+/// Check if the next server must be tried after the error
+///
+/// An invalid payload is rejected by all of them.
+#[inline]
+fn should_failover(error: &Error) -> bool {
+    !matches!(error, Error::InvalidPayload(..))
+}
+
+#[cfg(feature = "async-dispatcher")]
+impl MultiDispatcher<Async> {
+    /// Send payload according to the [`MultiMode`]
+    ///
+    /// Returns the result of every server tried, in priority order.
+    pub async fn send(&self, payload: &Payload) -> Vec<EndpointResult> {
+        match self.mode {
+            MultiMode::Failover => failover_async(&self.dispatchers, |d| d.send(payload)).await,
+            MultiMode::FanOut => fan_out_async(&self.dispatchers, |d| d.send(payload)).await,
+        }
+    }
+}
+
+/// Send to the servers in order, until the first success or an error not worth a failover
+#[cfg(feature = "async-dispatcher")]
+async fn failover_async<'a, T, F, Fut>(
+    dispatchers: &'a [Dispatcher<T>],
+    send: F,
+) -> Vec<EndpointResult>
+where
+    T: Clone,
+    F: Fn(&'a Dispatcher<T>) -> Fut,
+    Fut: Future<Output = Result<PublishedMessage, Error>>,
+{
+    let mut results: Vec<EndpointResult> = Vec::new();
+    for dispatcher in dispatchers.iter() {
+        let result = send(dispatcher).await;
+        let next: bool = result.as_ref().is_err_and(should_failover);
+        results.push(EndpointResult {
+            url: dispatcher.url().clone(),
+            result,
+        });
+        if !next {
+            break;
+        }
+    }
+    results
+}
+
+/// Send to all the servers concurrently
+#[cfg(feature = "async-dispatcher")]
+async fn fan_out_async<'a, T, F, Fut>(
+    dispatchers: &'a [Dispatcher<T>],
+    send: F,
+) -> Vec<EndpointResult>
+where
+    T: Clone,
+    F: Fn(&'a Dispatcher<T>) -> Fut,
+    Fut: Future<Output = Result<PublishedMessage, Error>>,
+{
+    join_all(dispatchers.iter().map(|dispatcher| {
+        let result = send(dispatcher);
+        async move {
+            EndpointResult {
+                url: dispatcher.url().clone(),
+                result: result.await,
+            }
+        }
+    }))
+    .await
+}
+
+#[cfg(feature = "blocking-dispatcher")]
+impl MultiDispatcher<Blocking> {
+    /// Send payload according to the [`MultiMode`]
+    ///
+    /// Returns the result of every server tried, in priority order.
+    /// With [`MultiMode::FanOut`], every server is sent from its own thread:
+    /// a panicking thread results in [`Error::WorkerPanicked`].
+    pub fn send(&self, payload: &Payload) -> Vec<EndpointResult> {
+        let send = |dispatcher: &Dispatcher<Blocking>| dispatcher.send(payload);
+        match self.mode {
+            MultiMode::Failover => failover_blocking(&self.dispatchers, send),
+            MultiMode::FanOut => fan_out_blocking(&self.dispatchers, send),
+        }
+    }
+}
+
+impl MultiDispatcher<Memory> {
+    /// Send payload according to the [`MultiMode`]
+    ///
+    /// Returns the result of every server tried, in priority order, as an already resolved future like [`Memory`].
+    pub fn send(&self, payload: &Payload) -> Ready<Vec<EndpointResult>> {
+        let send = |dispatcher: &Dispatcher<Memory>| dispatcher.send(payload).into_inner();
+        future::ready(match self.mode {
+            MultiMode::Failover => failover_blocking(&self.dispatchers, send),
+            // Already resolved: no need to send concurrently
+            MultiMode::FanOut => self
+                .dispatchers
+                .iter()
+                .map(|dispatcher| EndpointResult {
+                    url: dispatcher.url().clone(),
+                    result: send(dispatcher),
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Send to the servers in order, until the first success or an error not worth a failover
+fn failover_blocking<T, F>(dispatchers: &[Dispatcher<T>], send: F) -> Vec<EndpointResult>
+where
+    T: Clone,
+    F: Fn(&Dispatcher<T>) -> Result<PublishedMessage, Error>,
+{
+    let mut results: Vec<EndpointResult> = Vec::new();
+    for dispatcher in dispatchers.iter() {
+        let result = send(dispatcher);
+        let next: bool = result.as_ref().is_err_and(should_failover);
+        results.push(EndpointResult {
+            url: dispatcher.url().clone(),
+            result,
+        });
+        if !next {
+            break;
+        }
+    }
+    results
+}
+
+/// Send to all the servers concurrently, each one from its own thread
+#[cfg(feature = "blocking-dispatcher")]
+fn fan_out_blocking<T, F>(dispatchers: &[Dispatcher<T>], send: F) -> Vec<EndpointResult>
+where
+    T: Clone + Sync,
+    F: Fn(&Dispatcher<T>) -> Result<PublishedMessage, Error> + Sync,
+{
+    thread::scope(|s| {
+        let handles: Vec<_> = dispatchers
+            .iter()
+            .map(|dispatcher| s.spawn(|| send(dispatcher)))
+            .collect();
+
+        dispatchers
+            .iter()
+            .zip(handles)
+            .map(|(dispatcher, handle)| EndpointResult {
+                url: dispatcher.url().clone(),
+                result: handle.join().unwrap_or(Err(Error::WorkerPanicked)),
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dispatcher::testing::TestServer;
+    use crate::payload::Violation;
+
+    /// Nothing listening: the connection is refused
+    const DOWN: &str = "http://127.0.0.1:1";
+
+    fn memories(mode: MultiMode) -> (Vec<Memory>, MultiDispatcher<Memory>) {
+        let memories: Vec<Memory> = (0..3).map(|_| Memory::new()).collect();
+        let dispatchers = memories
+            .iter()
+            .enumerate()
+            .map(|(i, memory)| {
+                crate::DispatcherBuilder::new(format!("https://ntfy{i}.example.com"))
+                    .build_with_transport(memory.clone())
+                    .unwrap()
+            })
+            .collect();
+        (memories, MultiDispatcher::new(mode, dispatchers))
+    }
+
+    fn hosts(results: &[EndpointResult]) -> Vec<(String, bool)> {
+        results
+            .iter()
+            .map(|r| (r.url.authority().to_string(), r.is_ok()))
+            .collect()
+    }
+
+    fn authority(url: &str) -> String {
+        Url::parse(url).unwrap().authority().to_string()
+    }
+
+    #[test]
+    fn test_failover() {
+        let (memories, multi) = memories(MultiMode::Failover);
+        let payload = Payload::new("alerts").message("Disk full");
+
+        // Failover in order, until the first success
+        memories[0].fail_next(Error::Timeout);
+        let results = multi.send(&payload).into_inner();
+        assert_eq!(
+            hosts(&results),
+            vec![
+                (String::from("ntfy0.example.com"), false),
+                (String::from("ntfy1.example.com"), true)
+            ]
+        );
+        assert_eq!(memories[2].sent().len(), 0);
+
+        // Invalid payloads are rejected by all the servers
+        let results = multi.send(&Payload::new("my topic")).into_inner();
+        assert_eq!(
+            hosts(&results),
+            vec![(String::from("ntfy0.example.com"), false)]
+        );
+
+        // All failing
+        for memory in memories.iter() {
+            memory.fail_next(Error::Timeout);
+        }
+        let results = multi.send(&payload).into_inner();
+        assert!(results.iter().all(|r| !r.is_ok()));
+        assert_eq!(results.len(), 3);
+    }
+
+    #[test]
+    fn test_fan_out() {
+        let (memories, multi) = memories(MultiMode::FanOut);
+        let payload = Payload::new("alerts").message("Disk full");
+
+        // Fan-out to all, aggregating the failures
+        memories[1].fail_next(Error::Timeout);
+        let results = multi.send(&payload).into_inner();
+        assert_eq!(
+            hosts(&results),
+            vec![
+                (String::from("ntfy0.example.com"), true),
+                (String::from("ntfy1.example.com"), false),
+                (String::from("ntfy2.example.com"), true)
+            ]
+        );
+        let sent: Vec<usize> = memories.iter().map(|m| m.sent().len()).collect();
+        assert_eq!(sent, vec![1, 0, 1]);
+    }
+
+    #[test]
+    #[cfg(feature = "blocking-dispatcher")]
+    fn test_fan_out_panic() {
+        let (_, multi) = memories(MultiMode::FanOut);
+        let payload = Payload::new("alerts").message("Disk full");
+
+        // Panicking workers don't take down the caller
+        let results = fan_out_blocking(&multi.dispatchers, |dispatcher| {
+            if dispatcher.url().host_str() == Some("ntfy1.example.com") {
+                panic!("worker");
+            }
+            dispatcher.send(&payload).into_inner()
+        });
+        assert!(matches!(results[1].result, Err(Error::WorkerPanicked)));
+        assert!(results[0].is_ok() && results[2].is_ok());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "async-dispatcher")]
+    async fn test_async_multi() {
+        let server = TestServer::start(|_| std::time::Duration::ZERO);
+        let dispatchers: Vec<Dispatcher<Async>> = [DOWN, &server.url]
+            .into_iter()
+            .map(|url| crate::dispatcher::builder(url).build_async().unwrap())
+            .collect();
+        let expected = vec![(authority(DOWN), false), (authority(&server.url), true)];
+        let payload = Payload::new("alerts").message("Disk full");
+
+        let multi = MultiDispatcher::new(MultiMode::Failover, dispatchers.clone());
+        assert_eq!(hosts(&multi.send(&payload).await), expected);
+
+        let multi = MultiDispatcher::new(MultiMode::FanOut, dispatchers);
+        assert_eq!(hosts(&multi.send(&payload).await), expected);
+    }
+
+    #[test]
+    #[cfg(feature = "blocking-dispatcher")]
+    fn test_blocking_multi() {
+        let server = TestServer::start(|_| std::time::Duration::ZERO);
+        let dispatchers: Vec<Dispatcher<Blocking>> = [DOWN, &server.url]
+            .into_iter()
+            .map(|url| crate::dispatcher::builder(url).build_blocking().unwrap())
+            .collect();
+        let expected = vec![(authority(DOWN), false), (authority(&server.url), true)];
+        let payload = Payload::new("alerts").message("Disk full");
+
+        let multi = MultiDispatcher::new(MultiMode::Failover, dispatchers.clone());
+        assert_eq!(hosts(&multi.send(&payload)), expected);
+
+        let multi = MultiDispatcher::new(MultiMode::FanOut, dispatchers);
+        assert_eq!(hosts(&multi.send(&payload)), expected);
+    }
+
+    #[test]
+    fn test_should_failover() {
+        assert!(should_failover(&Error::server(502, b"")));
+        assert!(should_failover(&Error::Timeout));
+        assert!(!should_failover(&Error::InvalidPayload(vec![
+            Violation::InvalidTopic(String::from("my topic"))
+        ])));
+    }
+}
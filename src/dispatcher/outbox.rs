@@ -0,0 +1,750 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+#[cfg(feature = "blocking-dispatcher")]
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "async-dispatcher")]
+use tokio::runtime::Handle;
+#[cfg(feature = "async-dispatcher")]
+use tokio::sync::Notify;
+
+#[cfg(feature = "async-dispatcher")]
+use super::Async;
+#[cfg(feature = "blocking-dispatcher")]
+use super::Blocking;
+use super::Dispatcher;
+use super::retry::RetryPolicy;
+use crate::error::Error;
+use crate::payload::Payload;
+
+/// What to do when the outbox is full
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DropPolicy {
+    /// Drop the oldest pending payload, to make room for the new one
+    #[default]
+    Oldest,
+    /// Reject the new payload with [`Error::OutboxFull`]
+    Newest,
+}
+
+/// Failure reported to the [`OutboxPolicy::on_failure`] hook
+#[derive(Debug)]
+pub enum OutboxFailure {
+    /// Payload discarded without being delivered
+    ///
+    /// The error is the one of the last attempt, or [`Error::OutboxFull`] when dropped by [`DropPolicy::Oldest`].
+    Discarded { payload: Box<Payload>, error: Error },
+    /// The delivery couldn't be written to the journal
+    ///
+    /// The journal is rewritten at the next write. The payload may be delivered again after a restart.
+    Journal(Error),
+}
+
+type FailureHook = Arc<dyn Fn(OutboxFailure) + Send + Sync>;
+
+/// Outbox settings
+///
+/// By default, up to 1000 payloads are kept, dropping the oldest ones when full,
+/// and the deliveries are retried forever, with a backoff from 1 sec to 5 mins.
+#[derive(Clone)]
+pub struct OutboxPolicy {
+    max_entries: usize,
+    drop: DropPolicy,
+    retry: RetryPolicy,
+    on_failure: Option<FailureHook>,
+}
+
+impl fmt::Debug for OutboxPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutboxPolicy")
+            .field("max_entries", &self.max_entries)
+            .field("drop", &self.drop)
+            .field("retry", &self.retry)
+            .field("on_failure", &self.on_failure.is_some())
+            .finish()
+    }
+}
+
+impl Default for OutboxPolicy {
+    fn default() -> Self {
+        Self {
+            max_entries: 1000,
+            drop: DropPolicy::default(),
+            retry: RetryPolicy::new()
+                .max_attempts(u32::MAX)
+                .backoff(Duration::from_secs(1), Duration::from_secs(300)),
+            on_failure: None,
+        }
+    }
+}
+
+impl OutboxPolicy {
+    /// New default outbox policy
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Max number of pending payloads (default: 1000)
+    #[inline]
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+
+    /// What to do when the outbox is full (default: [`DropPolicy::Oldest`])
+    #[inline]
+    pub fn drop_policy(mut self, drop: DropPolicy) -> Self {
+        self.drop = drop;
+        self
+    }
+
+    /// Retry the failed deliveries according to the [`RetryPolicy`] (default: forever)
+    ///
+    /// Payloads failing with non-retryable errors (i.e. `403`), or exceeding the max attempts, are discarded
+    /// and reported to the [`OutboxPolicy::on_failure`] hook.
+    #[inline]
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Call the hook for the discarded payloads and the journal write errors (default: none)
+    ///
+    /// It's called by the delivery worker, or by `Outbox::enqueue` for the payloads dropped to make room:
+    /// it shouldn't block.
+    #[inline]
+    pub fn on_failure<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(OutboxFailure) + Send + Sync + 'static,
+    {
+        self.on_failure = Some(Arc::new(hook));
+        self
+    }
+}
+
+/// Journal record, one JSON object per line
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Record {
+    Push { id: u64, payload: Box<Payload> },
+    Ack { id: u64 },
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    id: u64,
+    payload: Payload,
+}
+
+/// Append-only on-disk journal of the pending payloads
+#[derive(Debug)]
+struct Journal {
+    path: PathBuf,
+    file: File,
+    pending: VecDeque<Entry>,
+    next_id: u64,
+    records: usize,
+    /// Set when a write failed, leaving the journal file in an unknown state
+    broken: bool,
+    max_entries: usize,
+    drop: DropPolicy,
+}
+
+impl Journal {
+    /// Open the journal, replaying the undelivered payloads
+    fn open(path: PathBuf, policy: &OutboxPolicy) -> Result<Self, Error> {
+        let mut pending: VecDeque<Entry> = VecDeque::new();
+        let mut next_id: u64 = 0;
+
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                // A truncated last line (crash while writing) is skipped
+                for record in content.lines().filter_map(|l| serde_json::from_str(l).ok()) {
+                    match record {
+                        Record::Push { id, payload } => {
+                            next_id = next_id.max(id + 1);
+                            pending.push_back(Entry {
+                                id,
+                                payload: *payload,
+                            });
+                        }
+                        Record::Ack { id } => pending.retain(|e| e.id != id),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        // Keep the most recent entries, if the max has been lowered
+        while pending.len() > policy.max_entries {
+            pending.pop_front();
+        }
+
+        let file: File = rewrite(&path, &pending)?;
+
+        Ok(Self {
+            path,
+            file,
+            records: pending.len(),
+            broken: false,
+            pending,
+            next_id,
+            max_entries: policy.max_entries,
+            drop: policy.drop,
+        })
+    }
+
+    /// Append the payload, returning the one dropped to make room, if any
+    fn push(&mut self, payload: Payload) -> Result<Option<Payload>, Error> {
+        let mut dropped: Option<Payload> = None;
+        if self.pending.len() >= self.max_entries {
+            match self.drop {
+                DropPolicy::Oldest => {
+                    if let Some(entry) = self.front() {
+                        self.ack(entry.id)?;
+                        dropped = Some(entry.payload);
+                    }
+                }
+                DropPolicy::Newest => return Err(Error::OutboxFull),
+            }
+        }
+
+        let entry = Entry {
+            id: self.next_id,
+            payload,
+        };
+
+        self.append(&Record::Push {
+            id: entry.id,
+            payload: Box::new(entry.payload.clone()),
+        })?;
+        self.file.sync_data()?;
+
+        self.next_id += 1;
+        self.pending.push_back(entry);
+
+        Ok(dropped)
+    }
+
+    #[inline]
+    fn front(&self) -> Option<Entry> {
+        self.pending.front().cloned()
+    }
+
+    /// Remove the entry, if still pending
+    fn ack(&mut self, id: u64) -> Result<(), Error> {
+        let len: usize = self.pending.len();
+        self.pending.retain(|e| e.id != id);
+        if self.pending.len() == len {
+            return Ok(());
+        }
+
+        // Drop the delivered records from time to time
+        if self.broken || self.records > self.pending.len() * 2 + 100 {
+            self.rewrite()
+        } else {
+            self.append(&Record::Ack { id })
+        }
+    }
+
+    fn append(&mut self, record: &Record) -> Result<(), Error> {
+        // The last line may be truncated: start from a clean journal
+        if self.broken {
+            self.rewrite()?;
+        }
+
+        let mut line: Vec<u8> = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if let Err(e) = self.file.write_all(&line) {
+            self.broken = true;
+            return Err(e.into());
+        }
+        self.records += 1;
+        Ok(())
+    }
+
+    fn rewrite(&mut self) -> Result<(), Error> {
+        self.broken = true;
+        self.file = rewrite(&self.path, &self.pending)?;
+        self.records = self.pending.len();
+        self.broken = false;
+        Ok(())
+    }
+}
+
+/// Write the pending entries to a new journal, replacing the old one atomically
+fn rewrite(path: &Path, pending: &VecDeque<Entry>) -> Result<File, Error> {
+    let mut tmp: PathBuf = path.to_path_buf();
+    tmp.as_mut_os_string().push(".tmp");
+
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    for entry in pending.iter() {
+        let record = Record::Push {
+            id: entry.id,
+            payload: Box::new(entry.payload.clone()),
+        };
+        serde_json::to_writer(&mut writer, &record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+
+    fs::rename(&tmp, path)?;
+
+    Ok(OpenOptions::new().append(true).open(path)?)
+}
+
+/// State shared with the delivery worker
+struct Shared {
+    journal: Mutex<Journal>,
+    shutdown: AtomicBool,
+    condvar: Condvar,
+    #[cfg(feature = "async-dispatcher")]
+    notify: Notify,
+    on_failure: Option<FailureHook>,
+}
+
+impl fmt::Debug for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared")
+            .field("journal", &self.journal)
+            .field("shutdown", &self.shutdown)
+            .finish()
+    }
+}
+
+impl Shared {
+    fn journal(&self) -> MutexGuard<'_, Journal> {
+        self.journal.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// Wake up the worker
+    fn notify(&self) {
+        self.condvar.notify_all();
+        #[cfg(feature = "async-dispatcher")]
+        self.notify.notify_one();
+    }
+
+    fn fail(&self, failure: OutboxFailure) {
+        if let Some(hook) = &self.on_failure {
+            hook(failure);
+        }
+    }
+
+    /// Remove the delivered or discarded entry, reporting the write errors
+    ///
+    /// If the ack can't be written, the payload is delivered again after a restart.
+    fn ack(&self, id: u64) {
+        if let Err(e) = self.journal().ack(id) {
+            self.fail(OutboxFailure::Journal(e));
+        }
+    }
+
+    /// Remove the delivered entry, or the discarded one reporting it
+    fn done(&self, entry: Entry, result: Result<(), Error>) {
+        self.ack(entry.id);
+        if let Err(error) = result {
+            self.fail(OutboxFailure::Discarded {
+                payload: Box::new(entry.payload),
+                error,
+            });
+        }
+    }
+}
+
+/// Durable outbox
+///
+/// Payloads are appended to an on-disk journal and delivered in the background by the
+/// wrapped [`Dispatcher`], retrying according to the [`OutboxPolicy`].
+/// Undelivered payloads are replayed when the outbox is opened again with the same journal.
+///
+/// Payloads are delivered one at a time, in order. The worker stops when the outbox is dropped.
+#[derive(Debug)]
+pub struct Outbox<T> {
+    shared: Arc<Shared>,
+    /// Client of the dispatcher, to validate the payloads before writing them
+    client: T,
+}
+
+impl<T> Outbox<T> {
+    fn open_journal<P>(path: P, policy: &OutboxPolicy) -> Result<Arc<Shared>, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let journal: Journal = Journal::open(path.as_ref().to_path_buf(), policy)?;
+        Ok(Arc::new(Shared {
+            journal: Mutex::new(journal),
+            shutdown: AtomicBool::new(false),
+            condvar: Condvar::new(),
+            #[cfg(feature = "async-dispatcher")]
+            notify: Notify::new(),
+            on_failure: policy.on_failure.clone(),
+        }))
+    }
+
+    /// Wake up the worker, reporting the payload dropped to make room, if any
+    fn enqueued(&self, dropped: Option<Payload>) {
+        self.shared.notify();
+        if let Some(payload) = dropped {
+            self.shared.fail(OutboxFailure::Discarded {
+                payload: Box::new(payload),
+                error: Error::OutboxFull,
+            });
+        }
+    }
+
+    /// Number of payloads not delivered yet
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.shared.journal().pending.len()
+    }
+
+    /// Check if all the payloads have been delivered
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Outbox<T> {
+    fn drop(&mut self) {
+        // Set while holding the lock, to not miss the wake up of the blocking worker
+        let _journal = self.shared.journal();
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        self.shared.notify();
+    }
+}
+
+#[cfg(feature = "async-dispatcher")]
+impl Outbox<Async> {
+    /// Open the outbox, replaying the undelivered payloads of the journal
+    ///
+    /// The delivery worker is spawned on the current `tokio` runtime: fails if there isn't any.
+    pub fn open<P>(
+        dispatcher: Dispatcher<Async>,
+        path: P,
+        policy: OutboxPolicy,
+    ) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let runtime: Handle = Handle::try_current().map_err(io::Error::other)?;
+        let shared: Arc<Shared> = Self::open_journal(path, &policy)?;
+        let client: Async = dispatcher.inner.clone();
+        runtime.spawn(deliver_async(dispatcher, shared.clone(), policy.retry));
+        Ok(Self { shared, client })
+    }
+
+    /// Append the payload to the journal, to be delivered in the background
+    ///
+    /// The payload is validated first, as the dispatcher does before sending it.
+    /// Returns once the payload has been written to disk, without blocking the runtime.
+    pub async fn enqueue(&self, payload: &Payload) -> Result<(), Error> {
+        self.client.validate(payload)?;
+
+        let shared: Arc<Shared> = self.shared.clone();
+        let payload: Payload = payload.clone();
+        let dropped: Option<Payload> =
+            tokio::task::spawn_blocking(move || shared.journal().push(payload))
+                .await
+                .map_err(|_| Error::WorkerPanicked)??;
+
+        self.enqueued(dropped);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async-dispatcher")]
+async fn deliver_async(dispatcher: Dispatcher<Async>, shared: Arc<Shared>, retry: RetryPolicy) {
+    let mut attempt: u32 = 0;
+
+    while !shared.is_shutdown() {
+        // The journal lock is held while writing to disk: don't block the runtime
+        let worker: Arc<Shared> = shared.clone();
+        let front: Option<Entry> =
+            match tokio::task::spawn_blocking(move || worker.journal().front()).await {
+                Ok(front) => front,
+                Err(..) => return,
+            };
+        let entry: Entry = match front {
+            Some(entry) => entry,
+            None => {
+                shared.notify.notified().await;
+                continue;
+            }
+        };
+
+        match dispatcher.send_and_forget(&entry.payload).await {
+            Err(e) if retry.should_retry(attempt, &e) => {
+                let deadline = tokio::time::Instant::now() + retry.backoff_for(attempt, &e);
+                attempt = attempt.saturating_add(1);
+
+                // Enqueued payloads don't interrupt the backoff, only the shutdown
+                while !shared.is_shutdown()
+                    && tokio::time::timeout_at(deadline, shared.notify.notified())
+                        .await
+                        .is_ok()
+                {}
+            }
+            result => {
+                let worker: Arc<Shared> = shared.clone();
+                let _ = tokio::task::spawn_blocking(move || worker.done(entry, result)).await;
+                attempt = 0;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "blocking-dispatcher")]
+impl Outbox<Blocking> {
+    /// Open the outbox, replaying the undelivered payloads of the journal
+    ///
+    /// The payloads are delivered by a background thread.
+    pub fn open<P>(
+        dispatcher: Dispatcher<Blocking>,
+        path: P,
+        policy: OutboxPolicy,
+    ) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let shared: Arc<Shared> = Self::open_journal(path, &policy)?;
+        let client: Blocking = dispatcher.inner.clone();
+        let worker: Arc<Shared> = shared.clone();
+        thread::Builder::new()
+            .name(String::from("ntfy-outbox"))
+            .spawn(move || deliver_blocking(dispatcher, worker, policy.retry))?;
+        Ok(Self { shared, client })
+    }
+
+    /// Append the payload to the journal, to be delivered in the background
+    ///
+    /// The payload is validated first, as the dispatcher does before sending it.
+    /// Returns once the payload has been written to disk.
+    pub fn enqueue(&self, payload: &Payload) -> Result<(), Error> {
+        self.client.validate(payload)?;
+        let dropped: Option<Payload> = self.shared.journal().push(payload.clone())?;
+        self.enqueued(dropped);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "blocking-dispatcher")]
+fn deliver_blocking(dispatcher: Dispatcher<Blocking>, shared: Arc<Shared>, retry: RetryPolicy) {
+    let mut attempt: u32 = 0;
+
+    loop {
+        let entry: Entry = {
+            let mut journal = shared.journal();
+            loop {
+                if shared.is_shutdown() {
+                    return;
+                }
+                match journal.front() {
+                    Some(entry) => break entry,
+                    None => {
+                        journal = shared
+                            .condvar
+                            .wait(journal)
+                            .unwrap_or_else(|e| e.into_inner())
+                    }
+                }
+            }
+        };
+
+        match dispatcher.send_and_forget(&entry.payload) {
+            Err(e) if retry.should_retry(attempt, &e) => {
+                let backoff = retry.backoff_for(attempt, &e);
+                attempt = attempt.saturating_add(1);
+
+                // Enqueued payloads don't interrupt the backoff, only the shutdown
+                let journal = shared.journal();
+                let _ = shared
+                    .condvar
+                    .wait_timeout_while(journal, backoff, |_| !shared.is_shutdown());
+            }
+            result => {
+                shared.done(entry, result);
+                attempt = 0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal_path(name: &str) -> PathBuf {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("ntfy-outbox-{}-{}.jsonl", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_journal_replay() {
+        let path: PathBuf = journal_path("replay");
+        let policy = OutboxPolicy::new();
+
+        let mut journal = Journal::open(path.clone(), &policy).unwrap();
+        journal.push(Payload::new("mytopic").message("1")).unwrap();
+        journal.push(Payload::new("mytopic").message("2")).unwrap();
+        let first: Entry = journal.front().unwrap();
+        journal.ack(first.id).unwrap();
+        drop(journal);
+
+        // Simulate a crash while writing
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"op":"push","id":2,"#).unwrap();
+
+        let mut journal = Journal::open(path.clone(), &policy).unwrap();
+        assert_eq!(journal.pending.len(), 1);
+        assert_eq!(
+            journal.front().unwrap().payload.message.as_deref(),
+            Some("2")
+        );
+
+        // IDs are not reused
+        journal.push(Payload::new("mytopic").message("3")).unwrap();
+        assert_eq!(journal.pending.back().unwrap().id, 2);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_journal_broken() {
+        let path: PathBuf = journal_path("broken");
+        let policy = OutboxPolicy::new();
+
+        let mut journal = Journal::open(path.clone(), &policy).unwrap();
+        journal.push(Payload::new("mytopic").message("1")).unwrap();
+        journal.push(Payload::new("mytopic").message("2")).unwrap();
+
+        // Simulate a failed write, truncating the last line
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"op":"ack","#).unwrap();
+        journal.broken = true;
+
+        journal.ack(0).unwrap();
+        assert!(!journal.broken);
+        journal.push(Payload::new("mytopic").message("3")).unwrap();
+        drop(journal);
+
+        let journal = Journal::open(path.clone(), &policy).unwrap();
+        let messages: Vec<Option<&str>> = journal
+            .pending
+            .iter()
+            .map(|e| e.payload.message.as_deref())
+            .collect();
+        assert_eq!(messages, vec![Some("2"), Some("3")]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_journal_drop_policy() {
+        let path: PathBuf = journal_path("drop");
+
+        let policy = OutboxPolicy::new().max_entries(2);
+        let mut journal = Journal::open(path.clone(), &policy).unwrap();
+        let dropped: Vec<Option<Payload>> = (0..3)
+            .map(|i| {
+                journal
+                    .push(Payload::new("mytopic").message(i.to_string()))
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(
+            dropped,
+            vec![None, None, Some(Payload::new("mytopic").message("0"))]
+        );
+        assert_eq!(journal.pending.len(), 2);
+        assert_eq!(
+            journal.front().unwrap().payload.message.as_deref(),
+            Some("1")
+        );
+        drop(journal);
+
+        let policy = policy.drop_policy(DropPolicy::Newest);
+        let mut journal = Journal::open(path.clone(), &policy).unwrap();
+        assert!(matches!(
+            journal.push(Payload::new("mytopic")),
+            Err(Error::OutboxFull)
+        ));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "blocking-dispatcher")]
+    fn test_enqueue_validation() {
+        let path: PathBuf = journal_path("validation");
+        // Nothing listening: the payloads stay pending
+        let dispatcher = crate::dispatcher::builder("http://127.0.0.1:1")
+            .build_blocking()
+            .unwrap();
+        let outbox = Outbox::<Blocking>::open(dispatcher, &path, OutboxPolicy::new()).unwrap();
+
+        assert!(matches!(
+            outbox.enqueue(&Payload::new("my topic")),
+            Err(Error::InvalidPayload(..))
+        ));
+        assert!(outbox.is_empty());
+
+        outbox.enqueue(&Payload::new("mytopic")).unwrap();
+        assert_eq!(outbox.len(), 1);
+        drop(outbox);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "async-dispatcher")]
+    fn test_open_without_runtime() {
+        let path: PathBuf = journal_path("runtime");
+        let dispatcher = crate::dispatcher::builder("http://127.0.0.1:1")
+            .build_async()
+            .unwrap();
+        assert!(Outbox::<Async>::open(dispatcher, &path, OutboxPolicy::new()).is_err());
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "async-dispatcher")]
+    async fn test_enqueue_async() {
+        let path: PathBuf = journal_path("async");
+        let dispatcher = crate::dispatcher::builder("http://127.0.0.1:1")
+            .build_async()
+            .unwrap();
+        let outbox = Outbox::<Async>::open(dispatcher, &path, OutboxPolicy::new()).unwrap();
+
+        assert!(matches!(
+            outbox.enqueue(&Payload::new("my topic")).await,
+            Err(Error::InvalidPayload(..))
+        ));
+        outbox.enqueue(&Payload::new("mytopic")).await.unwrap();
+        assert_eq!(outbox.len(), 1);
+        drop(outbox);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use crate::payload::Payload;
+
+/// Upload the messages longer than the max length as text attachments
+///
+/// The max length is the one of the [`ValidationPolicy`](crate::payload::ValidationPolicy),
+/// or [`MAX_MESSAGE_LEN`](crate::payload::MAX_MESSAGE_LEN) if it's disabled or without message limit.
+///
+/// The message is replaced by a short preview, while the full body is uploaded
+/// with the metadata of the payload. Payloads with [`Payload::attach`] are sent as they are.
+///
+/// <https://docs.ntfy.sh/publish/#attachments>
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OversizedPolicy {
+    filename: String,
+    preview_len: usize,
+}
+
+impl Default for OversizedPolicy {
+    fn default() -> Self {
+        Self {
+            filename: String::from("message.txt"),
+            preview_len: 200,
+        }
+    }
+}
+
+impl OversizedPolicy {
+    /// New default policy
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attachment file name, if [`Payload::filename`] isn't set (default: `message.txt`)
+    #[inline]
+    pub fn filename<S>(mut self, filename: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.filename = filename.into();
+        self
+    }
+
+    /// Max number of characters of the preview message (default: 200)
+    #[inline]
+    pub fn preview_len(mut self, len: usize) -> Self {
+        self.preview_len = len;
+        self
+    }
+
+    /// Split an oversized payload into the payload to upload, with the preview message, and the attachment body
+    pub(crate) fn split(&self, payload: &Payload, max_len: usize) -> Option<(Payload, String)> {
+        let message: &String = payload.message.as_ref()?;
+
+        if message.len() <= max_len || payload.attach.is_some() {
+            return None;
+        }
+
+        // The preview is sent as header: collapse new lines and other control characters
+        let mut preview: String = message
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .take(self.preview_len)
+            .collect();
+        preview.push('…');
+
+        let mut upload: Payload = payload.clone().message(preview);
+        if upload.filename.is_none() {
+            upload.filename = Some(self.filename.clone());
+        }
+
+        Some((upload, message.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::MAX_MESSAGE_LEN;
+
+    #[test]
+    fn test_split() {
+        let policy = OversizedPolicy::new().preview_len(10);
+
+        let payload = Payload::new("mytopic").message("Hello");
+        assert!(policy.split(&payload, MAX_MESSAGE_LEN).is_none());
+
+        let message: String = format!("Line 1\nLine 2\n{}", "a".repeat(MAX_MESSAGE_LEN));
+        let payload = Payload::new("mytopic").message(message.clone());
+        let (upload, body) = policy.split(&payload, MAX_MESSAGE_LEN).unwrap();
+        assert_eq!(upload.message.as_deref(), Some("Line 1 Lin…"));
+        assert_eq!(upload.filename.as_deref(), Some("message.txt"));
+        assert_eq!(body, message);
+        assert!(upload.validate().is_ok());
+
+        let payload = payload.filename("build.log");
+        let (upload, _) = policy.split(&payload, MAX_MESSAGE_LEN).unwrap();
+        assert_eq!(upload.filename.as_deref(), Some("build.log"));
+
+        // Larger limit of a self-hosted server
+        assert!(policy.split(&payload, 2 * MAX_MESSAGE_LEN).is_none());
+    }
+}
@@ -0,0 +1,328 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::time::Duration;
+
+use base64::engine::{Engine, general_purpose};
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use url::Url;
+
+use super::{OversizedPolicy, PublishMode};
+use crate::error::Error;
+use crate::payload::validation::Encoding;
+use crate::payload::{Action, MAX_MESSAGE_LEN, Payload, ValidationPolicy};
+
+/// Payload fields only supported as `X-*` headers
+const HEADER_ONLY_FIELDS: [&str; 5] = ["cache", "firebase", "unifiedpush", "poll_id", "template"];
+
+/// Build the URL of an endpoint below the server root (i.e. `https://ntfy.sh/<topic>`)
+pub(crate) fn endpoint_url(url: &Url, segments: &[&str]) -> Result<Url, Error> {
+    let mut url: Url = url.clone();
+    url.path_segments_mut()
+        .map_err(|_| Error::Url(url::ParseError::RelativeUrlWithCannotBeABaseBase))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+/// Parse the `Retry-After` header (delay in seconds)
+pub(crate) fn retry_after(value: Option<&HeaderValue>) -> Option<Duration> {
+    let secs: u64 = value?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+/// Check the payload according to the [`ValidationPolicy`], if set
+pub(crate) fn validate(
+    policy: Option<&ValidationPolicy>,
+    payload: &Payload,
+    encoding: Encoding,
+) -> Result<(), Error> {
+    match policy {
+        Some(policy) => policy.check(payload, encoding),
+        None => Ok(()),
+    }
+}
+
+/// Payload as it's going to be published
+pub(crate) enum Prepared<'a> {
+    /// Published in the [`PublishMode`]
+    Payload(&'a Payload),
+    /// Oversized message, uploaded as attachment along with the metadata
+    Upload(Box<Payload>, String),
+}
+
+/// Check the payload as it's going to be published, split by the [`OversizedPolicy`] if needed
+pub(crate) fn prepare<'a>(
+    policy: Option<&ValidationPolicy>,
+    oversized: Option<&OversizedPolicy>,
+    mode: PublishMode,
+    payload: &'a Payload,
+) -> Result<Prepared<'a>, Error> {
+    let max_len: usize = message_limit(policy);
+    match oversized.and_then(|o| o.split(payload, max_len)) {
+        Some((upload, body)) => {
+            validate(policy, &upload, Encoding::Headers)?;
+            Ok(Prepared::Upload(Box::new(upload), body))
+        }
+        None => {
+            validate(policy, payload, mode.into())?;
+            Ok(Prepared::Payload(payload))
+        }
+    }
+}
+
+/// Max message body size before uploading it as attachment, with the [`OversizedPolicy`]
+///
+/// Falls back to [`MAX_MESSAGE_LEN`] when the [`ValidationPolicy`] is disabled or without message limit.
+pub(crate) fn message_limit(policy: Option<&ValidationPolicy>) -> usize {
+    policy
+        .and_then(ValidationPolicy::message_limit)
+        .unwrap_or(MAX_MESSAGE_LEN)
+}
+
+/// Convert the payload metadata to ntfy `X-*` headers
+///
+/// The message body is **not** included.
+///
+/// <https://docs.ntfy.sh/publish/#list-of-all-parameters>
+pub(crate) fn payload_headers(payload: &Payload) -> Result<HeaderMap, Error> {
+    let mut headers: HeaderMap = header_only(payload)?;
+
+    if let Some(title) = &payload.title {
+        insert(&mut headers, "x-title", title)?;
+    }
+
+    if let Some(tags) = &payload.tags {
+        insert(&mut headers, "x-tags", &tags.join(","))?;
+    }
+
+    if let Some(priority) = &payload.priority {
+        insert(&mut headers, "x-priority", &priority.as_u8().to_string())?;
+    }
+
+    if let Some(actions) = &payload.actions {
+        // The JSON format is also accepted, for the values the short format can't quote
+        let value: String = match Action::to_header(actions) {
+            Ok(value) => value,
+            Err(..) => serde_json::to_string(actions)?,
+        };
+        insert(&mut headers, "x-actions", &value)?;
+    }
+
+    if let Some(click) = &payload.click {
+        insert(&mut headers, "x-click", click.as_str())?;
+    }
+
+    if let Some(attach) = &payload.attach {
+        insert(&mut headers, "x-attach", attach.as_str())?;
+    }
+
+    if let Some(markdown) = payload.markdown {
+        insert(
+            &mut headers,
+            "x-markdown",
+            if markdown { "yes" } else { "no" },
+        )?;
+    }
+
+    if let Some(icon) = &payload.icon {
+        insert(&mut headers, "x-icon", icon.as_str())?;
+    }
+
+    if let Some(filename) = &payload.filename {
+        insert(&mut headers, "x-filename", filename)?;
+    }
+
+    if let Some(delay) = &payload.delay {
+        insert(&mut headers, "x-delay", delay)?;
+    }
+
+    if let Some(email) = &payload.email {
+        insert(&mut headers, "x-email", email)?;
+    }
+
+    if let Some(sequence_id) = &payload.sequence_id {
+        insert(&mut headers, "x-sequence-id", sequence_id)?;
+    }
+
+    Ok(headers)
+}
+
+/// Convert the payload to the headers of an upload, including the message
+///
+/// The body is the attachment, so the message is sent as `X-Message`, with the new lines
+/// escaped as `\n` (decoded by the server), since they aren't allowed in headers.
+pub(crate) fn upload_headers(payload: &Payload) -> Result<HeaderMap, Error> {
+    let mut headers: HeaderMap = payload_headers(payload)?;
+    if let Some(message) = &payload.message {
+        let message: String = message.replace("\r\n", "\n").replace('\n', "\\n");
+        insert(&mut headers, "x-message", &message)?;
+    }
+    Ok(headers)
+}
+
+/// Convert the payload to the JSON body, without the header-only fields
+///
+/// The server ignores them in the JSON body: they are sent with [`header_only`] instead.
+///
+/// <https://docs.ntfy.sh/publish/#publish-as-json>
+pub(crate) fn json_body(payload: &Payload) -> Result<serde_json::Value, Error> {
+    let mut body = serde_json::to_value(payload)?;
+    if let Some(object) = body.as_object_mut() {
+        for field in HEADER_ONLY_FIELDS {
+            object.remove(field);
+        }
+    }
+    Ok(body)
+}
+
+/// Convert the payload options only supported as headers (cache, firebase, ...) to `X-*` headers
+pub(crate) fn header_only(payload: &Payload) -> Result<HeaderMap, Error> {
+    let mut headers = HeaderMap::new();
+
+    if let Some(false) = payload.cache {
+        insert(&mut headers, "x-cache", "no")?;
+    }
+
+    if let Some(false) = payload.firebase {
+        insert(&mut headers, "x-firebase", "no")?;
+    }
+
+    if let Some(true) = payload.unifiedpush {
+        insert(&mut headers, "x-unifiedpush", "1")?;
+    }
+
+    if let Some(poll_id) = &payload.poll_id {
+        insert(&mut headers, "x-poll-id", poll_id)?;
+    }
+
+    if let Some(template) = &payload.template {
+        insert(&mut headers, "x-template", template)?;
+    }
+
+    Ok(headers)
+}
+
+/// Insert header, encoding non-ASCII values as RFC 2047 encoded-word
+pub(crate) fn insert(
+    headers: &mut HeaderMap,
+    name: &'static str,
+    value: &str,
+) -> Result<(), Error> {
+    let value: HeaderValue = if value.is_ascii() {
+        HeaderValue::from_str(value)?
+    } else {
+        let b64: String = general_purpose::STANDARD.encode(value);
+        HeaderValue::from_str(&format!("=?UTF-8?B?{b64}?="))?
+    };
+    headers.insert(HeaderName::from_static(name), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::Priority;
+
+    #[test]
+    fn test_endpoint_url() {
+        let url = Url::parse("https://ntfy.sh").unwrap();
+        let url = endpoint_url(&url, &["mytopic"]).unwrap();
+        assert_eq!(url.as_str(), "https://ntfy.sh/mytopic");
+
+        let url = Url::parse("https://example.com/ntfy/").unwrap();
+        let url = endpoint_url(&url, &["mytopic"]).unwrap();
+        assert_eq!(url.as_str(), "https://example.com/ntfy/mytopic");
+    }
+
+    #[test]
+    fn test_message_limit() {
+        assert_eq!(message_limit(None), MAX_MESSAGE_LEN);
+        let policy = ValidationPolicy::new().max_message_len(Some(8192));
+        assert_eq!(message_limit(Some(&policy)), 8192);
+        let policy = ValidationPolicy::new().max_message_len(None);
+        assert_eq!(message_limit(Some(&policy)), MAX_MESSAGE_LEN);
+    }
+
+    #[test]
+    fn test_payload_headers() {
+        let payload = Payload::new("mytopic")
+            .message("Hello")
+            .title("Grüße")
+            .tags(["warning", "skull"])
+            .priority(Priority::High)
+            .markdown(true);
+        let headers = payload_headers(&payload).unwrap();
+
+        assert!(headers.get("x-message").is_none());
+        assert_eq!(headers["x-title"], "=?UTF-8?B?R3LDvMOfZQ==?=");
+        assert_eq!(headers["x-tags"], "warning,skull");
+        assert_eq!(headers["x-priority"], "4");
+        assert_eq!(headers["x-markdown"], "yes");
+        assert!(headers.get("x-cache").is_none());
+
+        let url = Url::parse("https://example.com/").unwrap();
+        let payload = Payload::new("mytopic").actions([Action::view("Open", url.clone())]);
+        let headers = payload_headers(&payload).unwrap();
+        assert_eq!(
+            headers["x-actions"],
+            "action=view, label=Open, url=https://example.com/"
+        );
+
+        let action = Action::http("Say", url).body(r#"{"text": "it's"}"#);
+        let payload = Payload::new("mytopic").actions([action.clone()]);
+        let headers = payload_headers(&payload).unwrap();
+        assert_eq!(
+            headers["x-actions"],
+            serde_json::to_string(&[Action::from(action)])
+                .unwrap()
+                .as_str()
+        );
+
+        let payload = Payload::new("mytopic")
+            .cache(false)
+            .template("github")
+            .sequence_id("deploy-42");
+        let headers = payload_headers(&payload).unwrap();
+        assert_eq!(headers["x-cache"], "no");
+        assert_eq!(headers["x-template"], "github");
+        assert_eq!(headers["x-sequence-id"], "deploy-42");
+    }
+
+    #[test]
+    fn test_upload_headers() {
+        let payload = Payload::new("mytopic")
+            .message("Line 1\nLine 2\r\nLine 3")
+            .filename("log.txt");
+        let headers = upload_headers(&payload).unwrap();
+        assert_eq!(headers["x-message"], r"Line 1\nLine 2\nLine 3");
+        assert_eq!(headers["x-filename"], "log.txt");
+
+        // Escaped in the encoded-words too
+        let payload = Payload::new("mytopic").message("Grüße\nLine 2");
+        let headers = upload_headers(&payload).unwrap();
+        assert_eq!(headers["x-message"], "=?UTF-8?B?R3LDvMOfZVxuTGluZSAy?=");
+    }
+
+    #[test]
+    fn test_json_body() {
+        let payload = Payload::new("mytopic")
+            .message("Hello")
+            .cache(false)
+            .firebase(false)
+            .template("github");
+
+        let body = json_body(&payload).unwrap();
+        assert_eq!(body["topic"], "mytopic");
+        assert_eq!(body["message"], "Hello");
+        assert!(body.get("cache").is_none());
+        assert!(body.get("firebase").is_none());
+        assert!(body.get("template").is_none());
+
+        let headers = header_only(&payload).unwrap();
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers["x-cache"], "no");
+        assert_eq!(headers["x-firebase"], "no");
+        assert_eq!(headers["x-template"], "github");
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::util;
+
+/// Retry policy with exponential backoff
+///
+/// By default, connection errors, server errors (`5xx`) and rate limited requests (`429`)
+/// are retried up to 3 times in total, starting with a backoff of 500 ms.
+///
+/// Streaming uploads are never retried, since the body can't be replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    connection_errors: bool,
+    server_errors: bool,
+    rate_limited: bool,
+    respect_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: true,
+            connection_errors: true,
+            server_errors: true,
+            rate_limited: true,
+            respect_retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// New default retry policy
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Max number of attempts, including the first one (default: 3)
+    #[inline]
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Backoff of the first retry, doubled at every attempt up to `max` (default: 500 ms and 30 secs)
+    #[inline]
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Randomize the backoff between 50% and 100% of its value (default: true)
+    #[inline]
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Retry on connection errors and timeouts (default: true)
+    #[inline]
+    pub fn retry_connection_errors(mut self, retry: bool) -> Self {
+        self.connection_errors = retry;
+        self
+    }
+
+    /// Retry on server errors, HTTP status `5xx` (default: true)
+    #[inline]
+    pub fn retry_server_errors(mut self, retry: bool) -> Self {
+        self.server_errors = retry;
+        self
+    }
+
+    /// Retry on rate limited requests, HTTP status `429` (default: true)
+    #[inline]
+    pub fn retry_rate_limited(mut self, retry: bool) -> Self {
+        self.rate_limited = retry;
+        self
+    }
+
+    /// Wait for the `Retry-After` returned by the server, instead of the backoff (default: true)
+    ///
+    /// If it's longer than the max backoff, the request isn't retried and the error is returned,
+    /// so a misbehaving server can't stall the send, nor get a retry sure to fail.
+    #[inline]
+    pub fn respect_retry_after(mut self, respect: bool) -> Self {
+        self.respect_retry_after = respect;
+        self
+    }
+
+    /// Check if the failed `attempt` (starting from `0`) must be retried
+    pub(crate) fn should_retry(&self, attempt: u32, error: &Error) -> bool {
+        attempt + 1 < self.max_attempts && self.is_retryable(error) && !self.waits_too_long(error)
+    }
+
+    /// Check if the `Retry-After` to respect is longer than the max backoff
+    fn waits_too_long(&self, error: &Error) -> bool {
+        match error {
+            Error::Server {
+                retry_after: Some(retry_after),
+                ..
+            } => self.respect_retry_after && *retry_after > self.max_backoff,
+            _ => false,
+        }
+    }
+
+    fn is_retryable(&self, error: &Error) -> bool {
+        match error {
+            Error::Server { status: 429, .. } => self.rate_limited,
+            Error::Server { status, .. } => self.server_errors && *status >= 500,
+            #[cfg(feature = "async-dispatcher")]
+            Error::Reqwest(e) => {
+                #[cfg(not(target_arch = "wasm32"))]
+                let connect: bool = e.is_connect();
+                #[cfg(target_arch = "wasm32")]
+                let connect: bool = false;
+                self.connection_errors && (connect || e.is_timeout() || e.is_request())
+            }
+            #[cfg(feature = "blocking-dispatcher")]
+            Error::Ureq(e) => {
+                self.connection_errors
+                    && matches!(
+                        e.as_ref(),
+                        ureq::Error::Io(..)
+                            | ureq::Error::Timeout(..)
+                            | ureq::Error::HostNotFound
+                            | ureq::Error::ConnectionFailed
+                    )
+            }
+            Error::Io(..) | Error::Timeout => self.connection_errors,
+            _ => false,
+        }
+    }
+
+    /// Time to wait before retrying the failed `attempt` (starting from `0`)
+    pub(crate) fn backoff_for(&self, attempt: u32, error: &Error) -> Duration {
+        if self.respect_retry_after {
+            if let Error::Server {
+                retry_after: Some(retry_after),
+                ..
+            } = error
+            {
+                return *retry_after;
+            }
+        }
+
+        let backoff: Duration = util::backoff(self.initial_backoff, self.max_backoff, attempt);
+
+        if self.jitter {
+            util::jitter(backoff)
+        } else {
+            backoff
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new()
+            .backoff(Duration::from_secs(1), Duration::from_secs(5))
+            .jitter(false);
+        let error = Error::server(503, b"");
+
+        assert_eq!(policy.backoff_for(0, &error), Duration::from_secs(1));
+        assert_eq!(policy.backoff_for(1, &error), Duration::from_secs(2));
+        assert_eq!(policy.backoff_for(2, &error), Duration::from_secs(4));
+        assert_eq!(policy.backoff_for(3, &error), Duration::from_secs(5));
+
+        let error = Error::server(429, b"").with_retry_after(Some(Duration::from_secs(3)));
+        assert_eq!(policy.backoff_for(0, &error), Duration::from_secs(3));
+
+        // Longer than the max backoff: not retried
+        let error = Error::server(429, b"").with_retry_after(Some(Duration::from_secs(3600)));
+        assert!(!policy.should_retry(0, &error));
+        let policy = policy.respect_retry_after(false);
+        assert!(policy.should_retry(0, &error));
+        assert_eq!(policy.backoff_for(0, &error), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new().max_attempts(2);
+        assert!(policy.should_retry(0, &Error::server(502, b"")));
+        assert!(policy.should_retry(0, &Error::server(429, b"")));
+        assert!(!policy.should_retry(1, &Error::server(502, b"")));
+        assert!(!policy.should_retry(0, &Error::server(403, b"")));
+        assert!(!policy.should_retry(0, &Error::EmptyResponse));
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! Local ntfy server for the dispatcher tests
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+type DelayFn = dyn Fn(&str) -> Duration + Send + Sync;
+
+#[derive(Default)]
+struct Stats {
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+/// Server publishing the JSON payloads, using the message as ID of the published message
+pub(crate) struct TestServer {
+    pub(crate) url: String,
+    stats: Arc<Stats>,
+}
+
+impl TestServer {
+    /// Start the server, answering each request after the delay returned for its message
+    pub(crate) fn start<F>(delay: F) -> Self
+    where
+        F: Fn(&str) -> Duration + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let stats: Arc<Stats> = Arc::new(Stats::default());
+        let delay: Arc<DelayFn> = Arc::new(delay);
+
+        let server_stats = stats.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let stats = server_stats.clone();
+                let delay = delay.clone();
+                thread::spawn(move || serve(stream, &stats, delay.as_ref()));
+            }
+        });
+
+        Self { url, stats }
+    }
+
+    /// Max number of requests handled at the same time
+    pub(crate) fn max_in_flight(&self) -> usize {
+        self.stats.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// Serve the requests of a keep-alive connection
+fn serve(stream: TcpStream, stats: &Stats, delay: &DelayFn) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+
+    loop {
+        // Request line and headers
+        let mut len: usize = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line) {
+                Ok(0) | Err(..) => return,
+                Ok(..) if line == "\r\n" => break,
+                Ok(..) => {
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            len = value.trim().parse().unwrap_or_default();
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut body: Vec<u8> = vec![0; len];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        let request: Value = serde_json::from_slice(&body).unwrap_or_default();
+        let message: &str = request["message"].as_str().unwrap_or_default();
+
+        let in_flight: usize = stats.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        stats.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        thread::sleep(delay(message));
+        stats.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        let published: String = json!({
+            "id": message,
+            "time": 1,
+            "event": "message",
+            "topic": request["topic"],
+        })
+        .to_string();
+        let response: String = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{published}",
+            published.len()
+        );
+        if writer.write_all(response.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,156 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use url::Url;
+
+#[cfg(feature = "async-dispatcher")]
+use super::Async;
+#[cfg(feature = "blocking-dispatcher")]
+use super::Blocking;
+use crate::error::Error;
+use crate::payload::{Payload, PublishedMessage};
+#[cfg(feature = "async-dispatcher")]
+use crate::util::BoxedFuture;
+
+/// Transport used by the [`Dispatcher`](super::Dispatcher) to reach the ntfy server
+///
+/// Implemented by [`Async`] (reqwest), [`Blocking`] (ureq) and [`Memory`](crate::memory::Memory).
+/// Custom transports can be used with [`DispatcherBuilder::build_with_transport`](super::DispatcherBuilder::build_with_transport).
+///
+/// Covers the requests common to all the transports: uploads (streaming a body),
+/// `send_many` and `flush_suppressed` depend on the client or on the policies, so they aren't part of it.
+pub trait PublishTransport: Clone {
+    /// `Result` for blocking transports, `Future` resolving to a `Result` for the async ones
+    type Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a;
+
+    /// Send payload to the server, returning the published message
+    fn send<'a>(&'a self, url: &'a Url, payload: &'a Payload)
+    -> Self::Output<'a, PublishedMessage>;
+
+    /// Send payload to the server, discarding the response
+    fn send_and_forget<'a>(&'a self, url: &'a Url, payload: &'a Payload) -> Self::Output<'a, ()>;
+
+    /// Mark the notification as read and dismiss it
+    fn clear<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        sequence_id: String,
+    ) -> Self::Output<'a, ()>;
+
+    /// Delete the notification
+    fn delete<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        sequence_id: String,
+    ) -> Self::Output<'a, ()>;
+
+    /// Output of a request that failed before reaching the transport (i.e. invalid payload)
+    fn fail<'a, T>(error: Error) -> Self::Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a;
+}
+
+#[cfg(feature = "async-dispatcher")]
+impl PublishTransport for Async {
+    type Output<'a, T>
+        = BoxedFuture<'a, Result<T, Error>>
+    where
+        Self: 'a,
+        T: 'a;
+
+    fn send<'a>(
+        &'a self,
+        url: &'a Url,
+        payload: &'a Payload,
+    ) -> Self::Output<'a, PublishedMessage> {
+        Box::pin(Async::send(self, url, payload))
+    }
+
+    fn send_and_forget<'a>(&'a self, url: &'a Url, payload: &'a Payload) -> Self::Output<'a, ()> {
+        Box::pin(Async::send_and_forget(self, url, payload))
+    }
+
+    fn clear<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        sequence_id: String,
+    ) -> Self::Output<'a, ()> {
+        Box::pin(async move { Async::clear(self, url, &topic, &sequence_id).await })
+    }
+
+    fn delete<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        sequence_id: String,
+    ) -> Self::Output<'a, ()> {
+        Box::pin(async move { Async::delete(self, url, &topic, &sequence_id).await })
+    }
+
+    fn fail<'a, T>(error: Error) -> Self::Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a,
+    {
+        Box::pin(async move { Err(error) })
+    }
+}
+
+#[cfg(feature = "blocking-dispatcher")]
+impl PublishTransport for Blocking {
+    type Output<'a, T>
+        = Result<T, Error>
+    where
+        Self: 'a,
+        T: 'a;
+
+    #[inline]
+    fn send<'a>(
+        &'a self,
+        url: &'a Url,
+        payload: &'a Payload,
+    ) -> Self::Output<'a, PublishedMessage> {
+        Blocking::send(self, url, payload)
+    }
+
+    #[inline]
+    fn send_and_forget<'a>(&'a self, url: &'a Url, payload: &'a Payload) -> Self::Output<'a, ()> {
+        Blocking::send_and_forget(self, url, payload)
+    }
+
+    #[inline]
+    fn clear<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        sequence_id: String,
+    ) -> Self::Output<'a, ()> {
+        Blocking::clear(self, url, &topic, &sequence_id)
+    }
+
+    #[inline]
+    fn delete<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        sequence_id: String,
+    ) -> Self::Output<'a, ()> {
+        Blocking::delete(self, url, &topic, &sequence_id)
+    }
+
+    #[inline]
+    fn fail<'a, T>(error: Error) -> Self::Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a,
+    {
+        Err(error)
+    }
+}
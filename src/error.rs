@@ -1,29 +1,65 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
-use std::fmt;
-#[cfg(feature = "blocking-dispatcher")]
-use std::io;
+use std::time::Duration;
+use std::{fmt, io};
 
+use http::StatusCode;
 use http::header::InvalidHeaderValue;
 
+use crate::payload::Violation;
+
 #[deprecated(since = "0.7.0", note = "Please use `Error` instead")]
 pub type NtfyError = Error;
 
 #[derive(Debug)]
 pub enum Error {
-    #[cfg(feature = "async-dispatcher")]
+    #[cfg(any(feature = "async-dispatcher", feature = "async-subscriber"))]
     Reqwest(reqwest::Error),
-    #[cfg(feature = "blocking-dispatcher")]
+    #[cfg(any(feature = "blocking-dispatcher", feature = "blocking-subscriber"))]
     Ureq(Box<ureq::Error>),
-    #[cfg(feature = "blocking-dispatcher")]
     Io(io::Error),
     #[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
     Tungstenite(tungstenite::Error),
     Serde(serde_json::Error),
     Url(url::ParseError),
     InvalidScheme(String),
+    /// Topic name doesn't match `[-_A-Za-z0-9]{1,64}`
+    InvalidTopic(String),
     InvalidHeaderValue(InvalidHeaderValue),
+    /// Action not matching the `X-Actions` header format
+    InvalidAction(String),
+    /// Payload not matching the ntfy server limits
+    InvalidPayload(Vec<Violation>),
+    /// Delay not supported or out of the allowed bounds
+    InvalidDelay(String),
+    /// Error returned by the ntfy server
+    ///
+    /// <https://docs.ntfy.sh/publish/#error-responses>
+    Server {
+        /// HTTP status code
+        status: u16,
+        /// ntfy error code (i.e. `40301`)
+        code: Option<u32>,
+        /// Error message
+        message: String,
+        /// Link to the documentation
+        link: Option<String>,
+        /// Time to wait before retrying, from the `Retry-After` header
+        retry_after: Option<Duration>,
+    },
+    /// Connection or read timed out
+    Timeout,
+    /// No event received within the keepalive timeout: the connection is considered dead
+    KeepaliveTimeout,
+    /// Outbox full, with the [`DropPolicy::Newest`](crate::dispatcher::DropPolicy::Newest)
+    OutboxFull,
+    /// Background worker thread panicked
+    WorkerPanicked,
+    /// TLS connector variant not supported by the blocking WebSocket transport
+    UnsupportedConnector,
+    /// Builder option not supported with a custom transport, i.e. the `retry_policy`
+    UnsupportedByTransport(&'static str),
     EmptyResponse,
     UnknownPriority,
 }
@@ -33,39 +69,154 @@ impl std::error::Error for Error {}
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            #[cfg(feature = "async-dispatcher")]
+            #[cfg(any(feature = "async-dispatcher", feature = "async-subscriber"))]
             Self::Reqwest(e) => write!(f, "{}", e),
-            #[cfg(feature = "blocking-dispatcher")]
+            #[cfg(any(feature = "blocking-dispatcher", feature = "blocking-subscriber"))]
             Self::Ureq(e) => write!(f, "{}", e),
-            #[cfg(feature = "blocking-dispatcher")]
             Self::Io(e) => write!(f, "{}", e),
             #[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
             Self::Tungstenite(e) => write!(f, "{}", e),
             Self::Serde(e) => write!(f, "{}", e),
             Self::Url(e) => write!(f, "{}", e),
             Self::InvalidScheme(scheme) => write!(f, "invalid scheme: {}", scheme),
+            Self::InvalidTopic(topic) => write!(f, "invalid topic: {}", topic),
             Self::InvalidHeaderValue(e) => write!(f, "{}", e),
+            Self::InvalidAction(action) => write!(f, "invalid action: {}", action),
+            Self::InvalidDelay(delay) => write!(f, "invalid delay: {}", delay),
+            Self::InvalidPayload(violations) => {
+                write!(f, "invalid payload: ")?;
+                for (i, violation) in violations.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", violation)?;
+                }
+                Ok(())
+            }
+            Self::Server {
+                status,
+                code,
+                message,
+                ..
+            } => match code {
+                Some(code) => write!(f, "server error {status} ({code}): {message}"),
+                None => write!(f, "server error {status}: {message}"),
+            },
+            Self::Timeout => write!(f, "Timeout"),
+            Self::KeepaliveTimeout => write!(f, "Keepalive timeout"),
+            Self::OutboxFull => write!(f, "Outbox full"),
+            Self::WorkerPanicked => write!(f, "Worker panicked"),
+            Self::UnsupportedConnector => write!(f, "Unsupported TLS connector"),
+            Self::UnsupportedByTransport(option) => {
+                write!(f, "{} not supported by custom transports", option)
+            }
             Self::EmptyResponse => write!(f, "Empty response"),
             Self::UnknownPriority => write!(f, "Unknown priority"),
         }
     }
 }
 
-#[cfg(feature = "async-dispatcher")]
+/// JSON error body returned by the ntfy server
+#[derive(Deserialize)]
+struct ServerErrorBody {
+    code: Option<u32>,
+    error: Option<String>,
+    link: Option<String>,
+}
+
+impl Error {
+    /// Construct a [`Error::Server`] from the HTTP status and the response body
+    pub(crate) fn server(status: u16, body: &[u8]) -> Self {
+        match serde_json::from_slice::<ServerErrorBody>(body) {
+            Ok(body) => Self::Server {
+                status,
+                code: body.code,
+                message: body.error.unwrap_or_else(|| reason(status)),
+                link: body.link,
+                retry_after: None,
+            },
+            Err(..) => {
+                let text = String::from_utf8_lossy(body);
+                let text: &str = text.trim();
+                Self::Server {
+                    status,
+                    code: None,
+                    message: if text.is_empty() {
+                        reason(status)
+                    } else {
+                        text.to_string()
+                    },
+                    link: None,
+                    retry_after: None,
+                }
+            }
+        }
+    }
+
+    /// Set the `Retry-After` of a [`Error::Server`]
+    #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+    pub(crate) fn with_retry_after(mut self, value: Option<Duration>) -> Self {
+        if let Self::Server { retry_after, .. } = &mut self {
+            *retry_after = value;
+        }
+        self
+    }
+
+    /// HTTP status code, if the error was returned by the server
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::Server { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Check if the request was rejected due to missing or invalid credentials (`401`)
+    #[inline]
+    pub fn is_unauthorized(&self) -> bool {
+        self.status() == Some(401)
+    }
+
+    /// Check if the access to the topic was denied (`403`), i.e. because the topic is reserved
+    #[inline]
+    pub fn is_forbidden(&self) -> bool {
+        self.status() == Some(403)
+    }
+
+    /// Check if the message or attachment exceeded the server limits (`413`)
+    #[inline]
+    pub fn is_too_large(&self) -> bool {
+        self.status() == Some(413)
+    }
+
+    /// Check if the request was rate limited by the server (`429`)
+    #[inline]
+    pub fn is_rate_limited(&self) -> bool {
+        self.status() == Some(429)
+    }
+}
+
+fn reason(status: u16) -> String {
+    StatusCode::from_u16(status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("unknown error")
+        .to_string()
+}
+
+#[cfg(any(feature = "async-dispatcher", feature = "async-subscriber"))]
 impl From<reqwest::Error> for Error {
     fn from(e: reqwest::Error) -> Self {
         Self::Reqwest(e)
     }
 }
 
-#[cfg(feature = "blocking-dispatcher")]
+#[cfg(any(feature = "blocking-dispatcher", feature = "blocking-subscriber"))]
 impl From<ureq::Error> for Error {
     fn from(e: ureq::Error) -> Self {
         Self::Ureq(Box::new(e))
     }
 }
 
-#[cfg(feature = "blocking-dispatcher")]
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self {
         Self::Io(e)
@@ -75,7 +226,23 @@ impl From<io::Error> for Error {
 #[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
 impl From<tungstenite::Error> for Error {
     fn from(e: tungstenite::Error) -> Self {
-        Self::Tungstenite(e)
+        match e {
+            // Handshake rejected by the server
+            tungstenite::Error::Http(res) => {
+                let body: &[u8] = res.body().as_deref().unwrap_or_default();
+                Self::server(res.status().as_u16(), body)
+            }
+            // Read timeout of the blocking socket
+            tungstenite::Error::Io(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Self::Timeout
+            }
+            e => Self::Tungstenite(e),
+        }
     }
 }
 
@@ -96,3 +263,20 @@ impl From<InvalidHeaderValue> for Error {
         Self::InvalidHeaderValue(e)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_error() {
+        let body = br#"{"code":40301,"http":403,"error":"forbidden","link":"https://ntfy.sh/docs/publish/#authentication"}"#;
+        let error = Error::server(403, body);
+        assert!(error.is_forbidden());
+        assert_eq!(error.to_string(), "server error 403 (40301): forbidden");
+
+        let error = Error::server(502, b"");
+        assert_eq!(error.status(), Some(502));
+        assert_eq!(error.to_string(), "server error 502: Bad Gateway");
+    }
+}
@@ -30,10 +30,12 @@ pub mod auth;
 #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
 pub mod dispatcher;
 pub mod error;
+pub mod memory;
 pub mod payload;
 pub mod prelude;
 #[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
 pub mod subscriber;
+mod util;
 
 pub use self::auth::Auth;
 #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
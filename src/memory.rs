@@ -0,0 +1,483 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+//! In-memory transport, to test the code using the dispatcher or the subscriber without a ntfy server
+
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::future::{self, Ready};
+#[cfg(feature = "async-subscriber")]
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+#[cfg(feature = "async-subscriber")]
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "async-subscriber")]
+use futures_util::stream::Stream;
+use url::Url;
+
+#[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+use crate::dispatcher::PublishTransport;
+use crate::error::Error;
+#[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+use crate::payload::{Payload, PublishedMessage};
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+use crate::payload::{ReceivedMessageType, ReceivedPayload};
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+use crate::subscriber::{ReconnectPolicy, Since, SubscribeTransport};
+
+#[derive(Debug, Default)]
+struct State {
+    #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+    sent: Vec<Payload>,
+    #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+    cleared: Vec<(String, String)>,
+    #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+    deleted: Vec<(String, String)>,
+    #[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+    received: VecDeque<Result<ReceivedPayload, Error>>,
+    failures: VecDeque<Error>,
+    last_id: u64,
+}
+
+impl State {
+    /// Take the next scripted failure
+    fn fail(&mut self) -> Result<(), Error> {
+        match self.failures.pop_front() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    fn next_id(&mut self) -> String {
+        self.last_id += 1;
+        format!("{:012}", self.last_id)
+    }
+}
+
+/// In-memory transport
+///
+/// Records the payloads sent and the notifications cleared or deleted by the [`Dispatcher`](crate::Dispatcher),
+/// after being checked by the dispatcher as with the built-in transports, and delivers the scripted messages to the [`Subscriber`](crate::Subscriber).
+/// The clones share the same state.
+///
+/// The outputs are already resolved futures: `.await` them in async code,
+/// or call [`Ready::into_inner`] in blocking code.
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "async-dispatcher")]
+/// # mod example {
+/// use ntfy::memory::Memory;
+/// use ntfy::{DispatcherBuilder, Payload};
+///
+/// # async fn run() -> Result<(), ntfy::Error> {
+/// let memory = Memory::new();
+/// let dispatcher = DispatcherBuilder::new("https://ntfy.sh").build_with_transport(memory.clone())?;
+///
+/// dispatcher.send(&Payload::new("alerts").message("Disk full")).await?;
+/// assert_eq!(memory.sent().len(), 1);
+/// # Ok(())
+/// # }
+/// # }
+/// ```
+///
+/// ```rust,no_run
+/// # #[cfg(feature = "blocking-subscriber")]
+/// # mod example {
+/// use ntfy::SubscriberBuilder;
+/// use ntfy::memory::Memory;
+///
+/// # fn run() -> Result<(), ntfy::Error> {
+/// let memory = Memory::new();
+/// let subscriber = SubscriberBuilder::new("https://ntfy.sh").build_with_transport(memory.clone())?;
+///
+/// memory.script_message("alerts", "Disk full");
+/// for message in subscriber.subscribe("alerts").into_inner()? {
+///     println!("{:?}", message?.message);
+/// }
+/// # Ok(())
+/// # }
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    state: Arc<Mutex<State>>,
+}
+
+impl Memory {
+    /// New empty in-memory transport
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Fail the next request (send, clear, delete, subscribe or poll) with the error
+    ///
+    /// Failures are used in the order they are added.
+    pub fn fail_next(&self, error: Error) {
+        self.state().failures.push_back(error);
+    }
+}
+
+#[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+impl Memory {
+    /// Payloads sent so far, in order
+    pub fn sent(&self) -> Vec<Payload> {
+        self.state().sent.clone()
+    }
+
+    /// Notifications cleared so far, as `(topic, sequence ID)`, in order
+    pub fn cleared(&self) -> Vec<(String, String)> {
+        self.state().cleared.clone()
+    }
+
+    /// Notifications deleted so far (including the canceled scheduled messages), as `(topic, sequence ID)`, in order
+    pub fn deleted(&self) -> Vec<(String, String)> {
+        self.state().deleted.clone()
+    }
+
+    fn publish(&self, payload: &Payload) -> Result<PublishedMessage, Error> {
+        let mut state = self.state();
+        state.fail()?;
+        state.sent.push(payload.clone());
+
+        Ok(PublishedMessage {
+            id: state.next_id(),
+            time: now(),
+            expires: None,
+            topic: payload.topic.clone(),
+            sequence_id: payload.sequence_id.clone(),
+            attachment: None,
+        })
+    }
+}
+
+#[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+impl PublishTransport for Memory {
+    type Output<'a, T>
+        = Ready<Result<T, Error>>
+    where
+        Self: 'a,
+        T: 'a;
+
+    fn send<'a>(
+        &'a self,
+        _url: &'a Url,
+        payload: &'a Payload,
+    ) -> Self::Output<'a, PublishedMessage> {
+        future::ready(self.publish(payload))
+    }
+
+    fn send_and_forget<'a>(&'a self, _url: &'a Url, payload: &'a Payload) -> Self::Output<'a, ()> {
+        future::ready(self.publish(payload).map(|_| ()))
+    }
+
+    fn clear<'a>(
+        &'a self,
+        _url: &'a Url,
+        topic: String,
+        sequence_id: String,
+    ) -> Self::Output<'a, ()> {
+        let mut state = self.state();
+        future::ready(
+            state
+                .fail()
+                .map(|()| state.cleared.push((topic, sequence_id))),
+        )
+    }
+
+    fn delete<'a>(
+        &'a self,
+        _url: &'a Url,
+        topic: String,
+        sequence_id: String,
+    ) -> Self::Output<'a, ()> {
+        let mut state = self.state();
+        future::ready(
+            state
+                .fail()
+                .map(|()| state.deleted.push((topic, sequence_id))),
+        )
+    }
+
+    fn fail<'a, T>(error: Error) -> Self::Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a,
+    {
+        future::ready(Err(error))
+    }
+}
+
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+impl Memory {
+    /// Script a message to be received by the next subscription to its topic
+    pub fn script(&self, payload: ReceivedPayload) {
+        self.state().received.push_back(Ok(payload));
+    }
+
+    /// Script a `message` event to be received by the next subscription to the topic
+    pub fn script_message<T, M>(&self, topic: T, message: M)
+    where
+        T: Into<String>,
+        M: Into<String>,
+    {
+        let mut state = self.state();
+        let payload = ReceivedPayload {
+            id: state.next_id(),
+            time: now(),
+            expires: None,
+            event: ReceivedMessageType::Message,
+            topic: topic.into(),
+            sequence_id: None,
+            message: Some(message.into()),
+            title: None,
+            tags: None,
+            priority: None,
+            click: None,
+            actions: None,
+            attachment: None,
+            icon: None,
+            content_type: None,
+            encoding: None,
+            extra: HashMap::new(),
+        };
+        state.received.push_back(Ok(payload));
+    }
+
+    /// Script an error to be received by the next subscription, whatever its topics
+    pub fn script_error(&self, error: Error) {
+        self.state().received.push_back(Err(error));
+    }
+
+    /// Take the scripted items for the comma-separated topics
+    fn take(
+        &self,
+        topics: &str,
+        errors: bool,
+    ) -> Result<VecDeque<Result<ReceivedPayload, Error>>, Error> {
+        let mut state = self.state();
+        state.fail()?;
+
+        let topics: Vec<&str> = topics.split(',').collect();
+        let mut taken = VecDeque::new();
+        let mut left = VecDeque::new();
+
+        for item in state.received.drain(..) {
+            let matches: bool = match &item {
+                Ok(payload) => topics.contains(&payload.topic.as_str()),
+                Err(..) => errors,
+            };
+
+            if matches {
+                taken.push_back(item);
+            } else {
+                left.push_back(item);
+            }
+        }
+
+        state.received = left;
+        Ok(taken)
+    }
+}
+
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+impl SubscribeTransport for Memory {
+    type Output<'a, T>
+        = Ready<Result<T, Error>>
+    where
+        Self: 'a,
+        T: 'a;
+
+    type Stream = MemoryStream;
+
+    type ReconnectingStream = MemoryStream;
+
+    fn subscribe<'a>(&'a self, _url: &'a Url, topics: String) -> Self::Output<'a, Self::Stream> {
+        future::ready(self.take(&topics, true).map(|items| MemoryStream { items }))
+    }
+
+    fn subscribe_with_reconnect<'a>(
+        &'a self,
+        _url: &'a Url,
+        topic: String,
+        _policy: ReconnectPolicy,
+    ) -> Self::Output<'a, Self::ReconnectingStream> {
+        future::ready(self.take(&topic, true).map(|items| MemoryStream { items }))
+    }
+
+    fn poll<'a>(
+        &'a self,
+        _url: &'a Url,
+        topic: String,
+        _since: Since,
+        _scheduled: bool,
+    ) -> Self::Output<'a, Vec<ReceivedPayload>> {
+        future::ready(
+            self.take(&topic, false)
+                .map(|items| items.into_iter().filter_map(Result::ok).collect()),
+        )
+    }
+
+    fn fail<'a, T>(error: Error) -> Self::Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a,
+    {
+        future::ready(Err(error))
+    }
+}
+
+/// Stream of the messages scripted in the [`Memory`] transport
+///
+/// Ends after the last scripted message.
+#[derive(Debug)]
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+pub struct MemoryStream {
+    items: VecDeque<Result<ReceivedPayload, Error>>,
+}
+
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+impl Iterator for MemoryStream {
+    type Item = Result<ReceivedPayload, Error>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.items.pop_front()
+    }
+}
+
+#[cfg(feature = "async-subscriber")]
+impl Stream for MemoryStream {
+    type Item = Result<ReceivedPayload, Error>;
+
+    #[inline]
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Poll::Ready(self.items.pop_front())
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.items.len(), Some(self.items.len()))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+    fn test_memory_dispatcher() {
+        let memory = Memory::new();
+        let dispatcher = crate::DispatcherBuilder::new("https://ntfy.sh")
+            .build_with_transport(memory.clone())
+            .unwrap();
+
+        let payload = Payload::new("alerts").message("Disk full");
+        let message: PublishedMessage = dispatcher.send(&payload).into_inner().unwrap();
+        assert_eq!(message.topic, "alerts");
+        assert_eq!(memory.sent(), vec![payload.clone()]);
+
+        memory.fail_next(Error::Timeout);
+        assert!(matches!(
+            dispatcher.send(&payload).into_inner(),
+            Err(Error::Timeout)
+        ));
+        assert!(
+            dispatcher
+                .send(&Payload::new("my topic"))
+                .into_inner()
+                .is_err()
+        );
+        assert_eq!(memory.sent().len(), 1);
+
+        // Checked according to the publish mode: the title is sent as header
+        let payload = Payload::new("alerts").title("Disk\nfull");
+        assert!(dispatcher.send(&payload).into_inner().is_ok());
+        let headers = crate::DispatcherBuilder::new("https://ntfy.sh")
+            .publish_mode(crate::dispatcher::PublishMode::Headers)
+            .build_with_transport(memory.clone())
+            .unwrap();
+        assert!(matches!(
+            headers.send_and_forget(&payload).into_inner(),
+            Err(Error::InvalidPayload(..))
+        ));
+        assert_eq!(memory.sent().len(), 2);
+
+        let dispatcher = crate::DispatcherBuilder::new("https://ntfy.sh")
+            .skip_validation()
+            .build_with_transport(memory.clone())
+            .unwrap();
+        assert!(
+            dispatcher
+                .send(&Payload::new("my topic"))
+                .into_inner()
+                .is_ok()
+        );
+        assert_eq!(memory.sent().len(), 3);
+
+        assert!(dispatcher.clear("alerts", "abc").into_inner().is_ok());
+        assert!(dispatcher.cancel_scheduled(&message).into_inner().is_ok());
+        memory.fail_next(Error::Timeout);
+        assert!(dispatcher.delete("alerts", "def").into_inner().is_err());
+        assert_eq!(
+            memory.cleared(),
+            vec![(String::from("alerts"), String::from("abc"))]
+        );
+        assert_eq!(memory.deleted(), vec![(message.topic, message.id)]);
+
+        assert!(matches!(
+            crate::DispatcherBuilder::new("https://ntfy.sh")
+                .retry_policy(crate::dispatcher::RetryPolicy::new())
+                .build_with_transport(memory.clone()),
+            Err(Error::UnsupportedByTransport("retry_policy"))
+        ));
+    }
+
+    #[test]
+    #[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+    fn test_memory_subscriber() {
+        let memory = Memory::new();
+        let subscriber = crate::SubscriberBuilder::new("https://ntfy.sh")
+            .build_with_transport(memory.clone())
+            .unwrap();
+
+        memory.script_message("alerts", "Disk full");
+        memory.script_message("backups", "Backup done");
+        memory.script_error(Error::KeepaliveTimeout);
+
+        let stream: MemoryStream = subscriber.subscribe("alerts").into_inner().unwrap();
+        let items: Vec<_> = stream.collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0].as_ref().unwrap().message.as_deref(),
+            Some("Disk full")
+        );
+        assert!(matches!(items[1], Err(Error::KeepaliveTimeout)));
+
+        let messages = subscriber.poll("backups", Since::All).into_inner().unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].topic, "backups");
+
+        assert!(
+            subscriber
+                .subscribe_many(["alerts", "back/ups"])
+                .into_inner()
+                .is_err()
+        );
+    }
+}
@@ -1,51 +1,621 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
 use serde_json::Value;
 use url::Url;
 
+use super::Violation;
+use crate::error::Error;
+
+/// Max number of actions per notification
+pub const MAX_ACTIONS: usize = 3;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ActionType {
     View,
     Broadcast,
     Http,
 }
 
+impl ActionType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::View => "view",
+            Self::Broadcast => "broadcast",
+            Self::Http => "http",
+        }
+    }
+}
+
+/// Notification action button
+///
+/// Since `0.10.0`, every action type is a variant with its own fields, built with [`Action::view`],
+/// [`Action::broadcast`] or [`Action::http`].
+/// To migrate from the previous struct:
+/// * `Action::new(ActionType::View, label, url)` becomes [`Action::view`] (same for [`Action::http`] and [`Action::broadcast`]);
+/// * the `action`, `label` and `url` fields are read with [`Action::action_type`], [`Action::label`] and [`Action::url`].
+///
+/// <https://docs.ntfy.sh/publish/#action-buttons>
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum Action {
+    /// Open a website or app
+    View(ViewAction),
+    /// Send an Android broadcast intent
+    Broadcast(BroadcastAction),
+    /// Send an HTTP request
+    Http(HttpAction),
+}
+
+/// Open a website or app
+///
+/// <https://docs.ntfy.sh/publish/#open-websiteapp>
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct Action {
-    pub action: ActionType,
+pub struct ViewAction {
     pub label: String,
     pub url: Url,
+    /// Clear the notification after the action button is tapped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub clear: Option<bool>,
-    pub body: Option<Value>,
+}
+
+/// Send an Android broadcast intent
+///
+/// <https://docs.ntfy.sh/publish/#send-android-broadcast>
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastAction {
+    pub label: String,
+    /// Android intent name (default: `io.heckel.ntfy.USER_ACTION`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub intent: Option<String>,
+    /// Android intent extras
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extras: Option<BTreeMap<String, String>>,
+    /// Clear the notification after the action button is tapped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clear: Option<bool>,
+}
+
+/// Send an HTTP request
+///
+/// <https://docs.ntfy.sh/publish/#send-http-request>
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpAction {
+    pub label: String,
+    pub url: Url,
+    /// HTTP method (default: `POST`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    /// HTTP headers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub headers: Option<BTreeMap<String, String>>,
+    /// HTTP body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// Clear the notification after the action button is tapped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clear: Option<bool>,
+}
+
+impl ViewAction {
+    /// Set clear
+    #[inline]
+    pub fn clear(mut self, clear: bool) -> Self {
+        self.clear = Some(clear);
+        self
+    }
+}
+
+impl BroadcastAction {
+    /// Set intent
+    #[inline]
+    pub fn intent<S>(mut self, intent: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.intent = Some(intent.into());
+        self
+    }
+
+    /// Add intent extra
+    pub fn extra<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.extras
+            .get_or_insert_with(BTreeMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Set clear
+    #[inline]
+    pub fn clear(mut self, clear: bool) -> Self {
+        self.clear = Some(clear);
+        self
+    }
+}
+
+impl HttpAction {
+    /// Set HTTP method
+    #[inline]
+    pub fn method<S>(mut self, method: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.method = Some(method.into());
+        self
+    }
+
+    /// Add HTTP header
+    pub fn header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.headers
+            .get_or_insert_with(BTreeMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
+    /// Set HTTP body
+    ///
+    /// Accepts strings or JSON values, like `json!({"temperature": 65})`, sent serialized.
+    #[inline]
+    pub fn body<V>(mut self, body: V) -> Self
+    where
+        V: Into<Value>,
+    {
+        self.body = Some(match body.into() {
+            Value::String(body) => body,
+            body => body.to_string(),
+        });
+        self
+    }
+
+    /// Set clear
+    #[inline]
+    pub fn clear(mut self, clear: bool) -> Self {
+        self.clear = Some(clear);
+        self
+    }
+}
+
+impl From<ViewAction> for Action {
+    #[inline]
+    fn from(action: ViewAction) -> Self {
+        Self::View(action)
+    }
+}
+
+impl From<BroadcastAction> for Action {
+    #[inline]
+    fn from(action: BroadcastAction) -> Self {
+        Self::Broadcast(action)
+    }
+}
+
+impl From<HttpAction> for Action {
+    #[inline]
+    fn from(action: HttpAction) -> Self {
+        Self::Http(action)
+    }
 }
 
 impl Action {
     /// Create new action
+    ///
+    /// Broadcast actions have no URL: it's passed to the Android app as the `url` intent extra.
+    #[deprecated(
+        since = "0.10.0",
+        note = "Use `Action::view`, `Action::broadcast` or `Action::http` instead"
+    )]
     pub fn new<S>(action: ActionType, label: S, url: Url) -> Self
     where
         S: Into<String>,
     {
-        Self {
-            action,
+        match action {
+            ActionType::View => Self::view(label, url).into(),
+            ActionType::Broadcast => Self::broadcast(label).extra("url", url).into(),
+            ActionType::Http => Self::http(label, url).into(),
+        }
+    }
+
+    /// New view action
+    ///
+    /// <https://docs.ntfy.sh/publish/#open-websiteapp>
+    pub fn view<S>(label: S, url: Url) -> ViewAction
+    where
+        S: Into<String>,
+    {
+        ViewAction {
             label: label.into(),
             url,
             clear: None,
+        }
+    }
+
+    /// New broadcast action
+    ///
+    /// <https://docs.ntfy.sh/publish/#send-android-broadcast>
+    pub fn broadcast<S>(label: S) -> BroadcastAction
+    where
+        S: Into<String>,
+    {
+        BroadcastAction {
+            label: label.into(),
+            intent: None,
+            extras: None,
+            clear: None,
+        }
+    }
+
+    /// New HTTP action
+    ///
+    /// <https://docs.ntfy.sh/publish/#send-http-request>
+    pub fn http<S>(label: S, url: Url) -> HttpAction
+    where
+        S: Into<String>,
+    {
+        HttpAction {
+            label: label.into(),
+            url,
+            method: None,
+            headers: None,
             body: None,
+            clear: None,
         }
     }
 
-    /// Set clear
-    #[inline]
-    pub fn clear(mut self, clear: bool) -> Self {
-        self.clear = Some(clear);
-        self
+    /// Action type
+    pub fn action_type(&self) -> ActionType {
+        match self {
+            Self::View(..) => ActionType::View,
+            Self::Broadcast(..) => ActionType::Broadcast,
+            Self::Http(..) => ActionType::Http,
+        }
     }
 
-    /// Set body
-    #[inline]
-    pub fn body(mut self, body: Value) -> Self {
-        self.body = Some(body);
-        self
+    /// Button label
+    pub fn label(&self) -> &str {
+        match self {
+            Self::View(action) => &action.label,
+            Self::Broadcast(action) => &action.label,
+            Self::Http(action) => &action.label,
+        }
+    }
+
+    /// URL opened or requested (view and HTTP actions only)
+    pub fn url(&self) -> Option<&Url> {
+        match self {
+            Self::View(action) => Some(&action.url),
+            Self::Http(action) => Some(&action.url),
+            Self::Broadcast(..) => None,
+        }
+    }
+
+    /// Parse the `X-Actions` header format (i.e. `view, Open portal, https://example.com; http, ...`)
+    ///
+    /// <https://docs.ntfy.sh/publish/#using-a-header>
+    pub fn from_header(header: &str) -> Result<Vec<Self>, Error> {
+        let actions: Vec<Self> = split_quoted(header, ';')
+            .into_iter()
+            .filter(|a| !a.trim().is_empty())
+            .map(|a| a.parse())
+            .collect::<Result<_, _>>()?;
+
+        if actions.len() > MAX_ACTIONS {
+            return Err(Error::InvalidPayload(vec![Violation::TooManyActions(
+                actions.len(),
+            )]));
+        }
+
+        Ok(actions)
+    }
+
+    /// Serialize to the `X-Actions` header format
+    ///
+    /// Fails if a value contains both `"` and `'`, since the format has no escaping.
+    pub fn to_header(actions: &[Self]) -> Result<String, Error> {
+        let mut header = String::new();
+        for action in actions.iter() {
+            if !header.is_empty() {
+                header.push_str("; ");
+            }
+            action.write_header(&mut header)?;
+        }
+        Ok(header)
+    }
+
+    /// Write single action of the `X-Actions` header format
+    fn write_header(&self, out: &mut String) -> Result<(), Error> {
+        let _ = write!(
+            out,
+            "action={}, label={}",
+            self.action_type().as_str(),
+            quote(self.label())?
+        );
+
+        let clear: &Option<bool> = match self {
+            Self::View(action) => {
+                let _ = write!(out, ", url={}", quote(action.url.as_str())?);
+                &action.clear
+            }
+            Self::Broadcast(action) => {
+                if let Some(intent) = &action.intent {
+                    let _ = write!(out, ", intent={}", quote(intent)?);
+                }
+                for (key, value) in action.extras.iter().flatten() {
+                    let _ = write!(out, ", extras.{key}={}", quote(value)?);
+                }
+                &action.clear
+            }
+            Self::Http(action) => {
+                let _ = write!(out, ", url={}", quote(action.url.as_str())?);
+                if let Some(method) = &action.method {
+                    let _ = write!(out, ", method={}", quote(method)?);
+                }
+                for (key, value) in action.headers.iter().flatten() {
+                    let _ = write!(out, ", headers.{key}={}", quote(value)?);
+                }
+                if let Some(body) = &action.body {
+                    let _ = write!(out, ", body={}", quote(body)?);
+                }
+                &action.clear
+            }
+        };
+
+        if let Some(clear) = clear {
+            let _ = write!(out, ", clear={clear}");
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Action {
+    type Err = Error;
+
+    /// Parse single action of the `X-Actions` header format
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut action: Option<ActionType> = None;
+        let mut label: Option<String> = None;
+        let mut url: Option<Url> = None;
+        let mut clear: Option<bool> = None;
+        let mut intent: Option<String> = None;
+        let mut extras: BTreeMap<String, String> = BTreeMap::new();
+        let mut method: Option<String> = None;
+        let mut headers: BTreeMap<String, String> = BTreeMap::new();
+        let mut body: Option<String> = None;
+
+        for (index, part) in split_quoted(s, ',').into_iter().enumerate() {
+            // Positional values: action, label and url
+            let (key, value) = match split_key_value(part) {
+                Some((key, value)) => (key, value),
+                None => match index {
+                    0 => ("action", unquote(part)),
+                    1 => ("label", unquote(part)),
+                    2 => ("url", unquote(part)),
+                    _ => return Err(Error::InvalidAction(s.trim().to_string())),
+                },
+            };
+
+            match key {
+                "action" => {
+                    action = Some(match value.to_lowercase().as_str() {
+                        "view" => ActionType::View,
+                        "broadcast" => ActionType::Broadcast,
+                        "http" => ActionType::Http,
+                        _ => return Err(Error::InvalidAction(s.trim().to_string())),
+                    })
+                }
+                "label" => label = Some(value.to_string()),
+                "url" => url = Some(Url::parse(value)?),
+                "clear" => clear = Some(value.eq_ignore_ascii_case("true")),
+                "intent" => intent = Some(value.to_string()),
+                "method" => method = Some(value.to_string()),
+                "body" => body = Some(value.to_string()),
+                key => match (key.strip_prefix("extras."), key.strip_prefix("headers.")) {
+                    (Some(key), _) => {
+                        extras.insert(key.to_string(), value.to_string());
+                    }
+                    (_, Some(key)) => {
+                        headers.insert(key.to_string(), value.to_string());
+                    }
+                    _ => return Err(Error::InvalidAction(s.trim().to_string())),
+                },
+            }
+        }
+
+        let (Some(action), Some(label)) = (action, label) else {
+            return Err(Error::InvalidAction(s.trim().to_string()));
+        };
+
+        match (action, url) {
+            (ActionType::View, Some(url)) => Ok(Self::View(ViewAction { label, url, clear })),
+            (ActionType::Broadcast, None) => Ok(Self::Broadcast(BroadcastAction {
+                label,
+                intent,
+                extras: (!extras.is_empty()).then_some(extras),
+                clear,
+            })),
+            (ActionType::Http, Some(url)) => Ok(Self::Http(HttpAction {
+                label,
+                url,
+                method,
+                headers: (!headers.is_empty()).then_some(headers),
+                body,
+                clear,
+            })),
+            _ => Err(Error::InvalidAction(s.trim().to_string())),
+        }
+    }
+}
+
+/// Split on the separator, ignoring the ones between quotes
+fn split_quoted(s: &str, separator: char) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut start: usize = 0;
+
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(..) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == separator => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            None => {}
+        }
+    }
+
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Split `key=value`, if the part before the `=` is a valid key
+fn split_key_value(part: &str) -> Option<(&str, &str)> {
+    let (key, value) = part.split_once('=')?;
+    let key: &str = key.trim();
+    let valid: bool = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if !valid {
+        return None;
+    }
+    Some((key, unquote(value)))
+}
+
+fn unquote(value: &str) -> &str {
+    let value: &str = value.trim();
+    for q in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(q).and_then(|v| v.strip_suffix(q)) {
+            return inner;
+        }
+    }
+    value
+}
+
+/// Quote the value if needed, with the quote type it doesn't contain
+fn quote(value: &str) -> Result<String, Error> {
+    let needs_quotes: bool =
+        value.contains([',', ';', '=', '"', '\'']) || value.trim() != value || value.is_empty();
+
+    match (value.contains('"'), value.contains('\'')) {
+        _ if !needs_quotes => Ok(value.to_string()),
+        (false, _) => Ok(format!("\"{value}\"")),
+        (true, false) => Ok(format!("'{value}'")),
+        (true, true) => Err(Error::InvalidAction(value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_json() {
+        let action = Action::http("Turn down", Url::parse("https://api.nest.com/").unwrap())
+            .method("PUT")
+            .header("Authorization", "Bearer zAzsx1sk..")
+            .body(r#"{"temperature": 65}"#);
+        let action = Action::from(action);
+        let json: String = serde_json::to_string(&action).unwrap();
+        assert_eq!(
+            json,
+            r#"{"action":"http","label":"Turn down","url":"https://api.nest.com/","method":"PUT","headers":{"Authorization":"Bearer zAzsx1sk.."},"body":"{\"temperature\": 65}"}"#
+        );
+        assert_eq!(serde_json::from_str::<Action>(&json).unwrap(), action);
+
+        // Broadcast action without URL
+        let json =
+            r#"{"id":"a1","action":"broadcast","label":"Take picture","extras":{"cmd":"pic"}}"#;
+        let action: Action = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            action,
+            Action::broadcast("Take picture").extra("cmd", "pic").into()
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_compat() {
+        let url = Url::parse("https://example.com/").unwrap();
+
+        let action =
+            Action::http("Turn down", url.clone()).body(serde_json::json!({"temperature": 65}));
+        assert_eq!(
+            action,
+            Action::http("Turn down", url.clone()).body(r#"{"temperature":65}"#)
+        );
+
+        let action = Action::new(ActionType::Broadcast, "Take picture", url.clone());
+        assert_eq!(
+            action,
+            Action::broadcast("Take picture")
+                .extra("url", "https://example.com/")
+                .into()
+        );
+        assert_eq!(action.url(), None);
+        assert_eq!(
+            Action::new(ActionType::View, "Open", url.clone()).url(),
+            Some(&url)
+        );
+    }
+
+    #[test]
+    fn test_header_format() {
+        let header = r#"view, Open portal, https://home.nest.com/, clear=true; http, "Turn down, now", https://api.nest.com/, method=PUT, headers.Authorization=Bearer zAzsx1sk.., body='{"temperature": 65}'; action=broadcast, label=Take picture, extras.cmd=pic"#;
+        let actions: Vec<Action> = Action::from_header(header).unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                Action::view("Open portal", Url::parse("https://home.nest.com/").unwrap())
+                    .clear(true)
+                    .into(),
+                Action::http(
+                    "Turn down, now",
+                    Url::parse("https://api.nest.com/").unwrap()
+                )
+                .method("PUT")
+                .header("Authorization", "Bearer zAzsx1sk..")
+                .body(r#"{"temperature": 65}"#)
+                .into(),
+                Action::broadcast("Take picture").extra("cmd", "pic").into(),
+            ]
+        );
+
+        // Round trip
+        let header: String = Action::to_header(&actions).unwrap();
+        assert_eq!(Action::from_header(&header).unwrap(), actions);
+
+        let actions = Action::from_header("view, Search, https://example.com/?q=ntfy").unwrap();
+        assert_eq!(
+            actions,
+            vec![Action::view("Search", Url::parse("https://example.com/?q=ntfy").unwrap()).into()]
+        );
+
+        assert!(Action::from_header("broadcast, Take picture, https://example.com").is_err());
+
+        // No quote type left
+        let action = Action::http("Say", Url::parse("https://example.com/").unwrap())
+            .body(r#"{"text": "it's"}"#);
+        assert!(Action::to_header(&[action.into()]).is_err());
+        let action = Action::broadcast(r#"Say "hi""#);
+        assert_eq!(
+            Action::to_header(&[action.into()]).unwrap(),
+            r#"action=broadcast, label='Say "hi"'"#
+        );
+        assert!(Action::from_header("view, A, https://a.com; view, B, https://b.com; view, C, https://c.com; view, D, https://d.com").is_err());
     }
 }
@@ -0,0 +1,227 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+
+/// Min delay accepted by the server
+pub const MIN_DELAY: Duration = Duration::from_secs(10);
+/// Max delay accepted by the server (default config)
+pub const MAX_DELAY: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// Delayed delivery
+///
+/// The durations and timestamps must be between [`MIN_DELAY`] and [`MAX_DELAY`] from now,
+/// so it can only be built with [`Delay::after`], [`Delay::at`], [`Delay::unix`] or parsed.
+///
+/// <https://docs.ntfy.sh/publish/#scheduled-delivery>
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Delay(Inner);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Inner {
+    /// Deliver after the duration (i.e. `30m`)
+    After(Duration),
+    /// Deliver at the Unix time stamp
+    At(u64),
+    /// Natural language (i.e. `tomorrow, 10am`), validated by the server
+    Natural(String),
+}
+
+impl Delay {
+    /// Deliver after the duration
+    pub fn after(duration: Duration) -> Result<Self, Error> {
+        check_bounds(duration)?;
+        Ok(Self(Inner::After(duration)))
+    }
+
+    /// Deliver at the time
+    pub fn at(time: SystemTime) -> Result<Self, Error> {
+        let timestamp: u64 = time
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Error::InvalidDelay(format!("{time:?}")))?
+            .as_secs();
+        Self::unix(timestamp)
+    }
+
+    /// Deliver at the Unix time stamp
+    pub fn unix(timestamp: u64) -> Result<Self, Error> {
+        let now: u64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        match timestamp.checked_sub(now) {
+            Some(secs) => check_bounds(Duration::from_secs(secs))?,
+            None => return Err(Error::InvalidDelay(timestamp.to_string())),
+        }
+        Ok(Self(Inner::At(timestamp)))
+    }
+}
+
+impl FromStr for Delay {
+    type Err = Error;
+
+    /// Parse Unix time stamp (i.e. `1639194738`), duration (i.e. `30m`, `2 hours`)
+    /// or natural language (i.e. `tomorrow, 10am`, `Tuesday, 7:30pm`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value: String = s.trim().to_lowercase();
+
+        if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+            let timestamp: u64 = value
+                .parse()
+                .map_err(|_| Error::InvalidDelay(s.to_string()))?;
+            return Self::unix(timestamp);
+        }
+
+        if let Some(duration) = parse_duration(&value) {
+            return Self::after(duration);
+        }
+
+        if is_natural(&value) {
+            return Ok(Self(Inner::Natural(s.trim().to_string())));
+        }
+
+        Err(Error::InvalidDelay(s.to_string()))
+    }
+}
+
+impl fmt::Display for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Inner::After(duration) => write!(f, "{}s", duration.as_secs()),
+            Inner::At(timestamp) => write!(f, "{timestamp}"),
+            Inner::Natural(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+fn check_bounds(duration: Duration) -> Result<(), Error> {
+    if duration < MIN_DELAY || duration > MAX_DELAY {
+        return Err(Error::InvalidDelay(format!("{}s", duration.as_secs())));
+    }
+    Ok(())
+}
+
+/// Parse duration like `30m`, `30 min` or `2 days`
+fn parse_duration(value: &str) -> Option<Duration> {
+    let split: usize = value.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = value.split_at(split);
+    let amount: u64 = amount.parse().ok()?;
+    let secs: u64 = match unit.trim_start() {
+        "s" | "sec" | "secs" | "second" | "seconds" => 1,
+        "m" | "min" | "mins" | "minute" | "minutes" => 60,
+        "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(amount.checked_mul(secs)?))
+}
+
+/// Check natural language like `10am`, `tomorrow, 10am` or `tuesday 7:30pm`
+fn is_natural(value: &str) -> bool {
+    if is_time(value) {
+        return true;
+    }
+
+    let (day, time) = match value.split_once([',', ' ']) {
+        Some((day, time)) => (Some(day.trim()), time.trim()),
+        None => (None, value),
+    };
+
+    let valid_day: bool = match day {
+        Some(day) => day == "today" || day == "tomorrow" || WEEKDAYS.contains(&day),
+        None => true,
+    };
+
+    // Days without time (i.e. `tomorrow`)
+    if day.is_none() && (value == "tomorrow" || WEEKDAYS.contains(&value)) {
+        return true;
+    }
+
+    valid_day && is_time(time)
+}
+
+/// Check time like `10am`, `10:30 pm` or `22:00`
+fn is_time(value: &str) -> bool {
+    let (clock, meridiem) = match value
+        .strip_suffix("am")
+        .or_else(|| value.strip_suffix("pm"))
+    {
+        Some(clock) => (clock.trim_end(), true),
+        None => (value, false),
+    };
+
+    let (hours, minutes) = match clock.split_once(':') {
+        Some((hours, minutes)) => (hours, Some(minutes)),
+        None => (clock, None),
+    };
+
+    let max_hours: u8 = if meridiem { 12 } else { 23 };
+    let valid_hours: bool = hours.len() <= 2
+        && hours
+            .parse::<u8>()
+            .is_ok_and(|h| h <= max_hours && (!meridiem || h >= 1));
+    let valid_minutes: bool = match minutes {
+        Some(minutes) => minutes.len() == 2 && minutes.parse::<u8>().is_ok_and(|m| m < 60),
+        // `22` alone is not a time
+        None => meridiem,
+    };
+
+    valid_hours && valid_minutes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_delay() {
+        assert_eq!(
+            Delay::from_str("30m").unwrap(),
+            Delay::after(Duration::from_secs(1800)).unwrap()
+        );
+        assert_eq!(
+            Delay::from_str("2 days").unwrap(),
+            Delay::after(Duration::from_secs(172800)).unwrap()
+        );
+        assert_eq!(
+            Delay::from_str("Tomorrow, 10am").unwrap(),
+            Delay(Inner::Natural(String::from("Tomorrow, 10am")))
+        );
+        assert!(Delay::from_str("tuesday 7:30pm").is_ok());
+        assert!(Delay::from_str("22:00").is_ok());
+        assert!(Delay::from_str("10 am").is_ok());
+
+        assert!(Delay::from_str("30 minuts").is_err());
+        assert!(Delay::from_str("5s").is_err());
+        assert!(Delay::from_str("4d").is_err());
+        assert!(Delay::from_str("13pm").is_err());
+        assert!(Delay::from_str("1").is_err());
+
+        assert_eq!(Delay::from_str("1h").unwrap().to_string(), "3600s");
+    }
+
+    #[test]
+    fn test_delay_at() {
+        let now = SystemTime::now();
+        assert!(Delay::at(now + Duration::from_secs(60)).is_ok());
+        assert!(Delay::at(now - Duration::from_secs(60)).is_err());
+        assert!(Delay::at(now + Duration::from_secs(4 * 24 * 60 * 60)).is_err());
+
+        assert!(Delay::after(Duration::from_secs(5)).is_err());
+        assert!(Delay::after(Duration::from_secs(4 * 24 * 60 * 60)).is_err());
+    }
+}
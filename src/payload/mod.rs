@@ -1,13 +1,29 @@
 // Copyright (c) 2022 Yuki Kishimoto
 // Distributed under the MIT software license
 
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+use std::borrow::Cow;
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+use base64::engine::{Engine, general_purpose};
 use url::Url;
 
+use self::validation::Encoding;
+use crate::error::Error;
+use crate::util;
+
 pub mod action;
+pub mod delay;
 pub mod priority;
+pub mod validation;
 
-pub use self::action::{Action, ActionType};
+pub use self::action::{Action, ActionType, BroadcastAction, HttpAction, MAX_ACTIONS, ViewAction};
+pub use self::delay::{Delay, MAX_DELAY, MIN_DELAY};
 pub use self::priority::Priority;
+pub use self::validation::{MAX_MESSAGE_LEN, ValidationPolicy, Violation};
 
 /// JSON payload
 ///
@@ -42,6 +58,34 @@ pub struct Payload {
     pub delay: Option<String>,
     /// E-mail address for e-mail notifications
     pub email: Option<String>,
+    /// Set to `false` to not cache the message on the server
+    ///
+    /// Always sent as `X-*` header, since the server ignores it in the JSON body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<bool>,
+    /// Set to `false` to not forward the message to Firebase
+    ///
+    /// Always sent as `X-*` header, since the server ignores it in the JSON body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firebase: Option<bool>,
+    /// Set to `true` to publish as UnifiedPush message
+    ///
+    /// Always sent as `X-*` header, since the server ignores it in the JSON body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unifiedpush: Option<bool>,
+    /// Poll ID for iOS poll requests
+    ///
+    /// Always sent as `X-*` header, since the server ignores it in the JSON body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll_id: Option<String>,
+    /// Message template (`yes` or the name of a pre-defined template)
+    ///
+    /// Always sent as `X-*` header, since the server ignores it in the JSON body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Sequence ID, to update a previously published notification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sequence_id: Option<String>,
 }
 
 impl Payload {
@@ -94,11 +138,14 @@ impl Payload {
     }
 
     /// Set actions
-    pub fn actions<I>(mut self, actions: I) -> Self
+    ///
+    /// Max [`MAX_ACTIONS`] actions are allowed, see [`Payload::validate`].
+    pub fn actions<I, A>(mut self, actions: I) -> Self
     where
-        I: IntoIterator<Item = Action>,
+        I: IntoIterator<Item = A>,
+        A: Into<Action>,
     {
-        self.actions = Some(actions.into_iter().collect());
+        self.actions = Some(actions.into_iter().map(Into::into).collect());
         self
     }
 
@@ -143,6 +190,8 @@ impl Payload {
     }
 
     /// Set delay
+    ///
+    /// The value is validated by the server only, see [`Payload::schedule`] for the typed version.
     #[inline]
     pub fn delay<S>(mut self, delay: S) -> Self
     where
@@ -152,6 +201,15 @@ impl Payload {
         self
     }
 
+    /// Schedule the delivery
+    ///
+    /// <https://docs.ntfy.sh/publish/#scheduled-delivery>
+    #[inline]
+    pub fn schedule(mut self, delay: Delay) -> Self {
+        self.delay = Some(delay.to_string());
+        self
+    }
+
     /// Set email
     #[inline]
     pub fn email<S>(mut self, email: S) -> Self
@@ -161,6 +219,79 @@ impl Payload {
         self.email = Some(email.into());
         self
     }
+
+    /// Set cache
+    ///
+    /// <https://docs.ntfy.sh/publish/#message-caching>
+    #[inline]
+    pub fn cache(mut self, cache: bool) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Set firebase
+    ///
+    /// <https://docs.ntfy.sh/publish/#disable-firebase>
+    #[inline]
+    pub fn firebase(mut self, firebase: bool) -> Self {
+        self.firebase = Some(firebase);
+        self
+    }
+
+    /// Set UnifiedPush
+    ///
+    /// <https://docs.ntfy.sh/publish/#unifiedpush>
+    #[inline]
+    pub fn unifiedpush(mut self, unifiedpush: bool) -> Self {
+        self.unifiedpush = Some(unifiedpush);
+        self
+    }
+
+    /// Set poll ID
+    #[inline]
+    pub fn poll_id<S>(mut self, poll_id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.poll_id = Some(poll_id.into());
+        self
+    }
+
+    /// Set template
+    ///
+    /// <https://docs.ntfy.sh/publish/#message-templating>
+    #[inline]
+    pub fn template<S>(mut self, template: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.template = Some(template.into());
+        self
+    }
+
+    /// Set sequence ID
+    ///
+    /// Notifications with the same sequence ID replace each other (i.e. progress updates),
+    /// and can be cleared or deleted with the dispatcher.
+    ///
+    /// <https://docs.ntfy.sh/publish/#updating-deleting-notifications>
+    #[inline]
+    pub fn sequence_id<S>(mut self, sequence_id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.sequence_id = Some(sequence_id.into());
+        self
+    }
+
+    /// Check the payload against the default [`ValidationPolicy`]
+    ///
+    /// All the rules are checked, whether the fields are sent as headers or not.
+    /// The dispatcher checks only the ones of its [`PublishMode`](crate::dispatcher::PublishMode).
+    /// All the violations are returned in [`Error::InvalidPayload`].
+    pub fn validate(&self) -> Result<(), Error> {
+        ValidationPolicy::default().check(self, Encoding::Any)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
@@ -179,34 +310,80 @@ pub enum ReceivedMessageType {
 ///
 /// <https://docs.ntfy.sh/subscribe/api/#json-message-format>
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
-#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
 pub struct ReceivedAttachment {
     /// Name of the attachment.
-    name: String,
+    pub name: String,
     /// URL of the attachment.
-    url: Url,
+    pub url: Url,
     /// Mime type of the attachment.
     #[serde(alias = "type")]
-    mime_type: Option<String>,
+    pub mime_type: Option<String>,
     /// Size of the attachment in bytes.
-    size: Option<u32>,
+    pub size: Option<u64>,
     /// Attachment expiry date as Unix time stamp.
-    expires: Option<u32>,
+    pub expires: Option<u64>,
+}
+
+impl ReceivedAttachment {
+    /// Attachment expiry date
+    #[inline]
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires.map(util::unix_time)
+    }
+}
+
+/// Message returned by the server after publishing
+///
+/// <https://docs.ntfy.sh/publish/#publish-as-json>
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+pub struct PublishedMessage {
+    /// Randomly chosen message identifier.
+    pub id: String,
+    /// Message date time, as Unix time stamp.
+    pub time: u64,
+    /// Unix time stamp indicating when the message will be deleted,
+    /// not set if Cache: no is sent
+    pub expires: Option<u64>,
+    /// Topic the message was published to
+    pub topic: String,
+    /// Sequence ID for updating/deleting notifications
+    pub sequence_id: Option<String>,
+    /// Details about an attachment (name, URL, size, ...)
+    pub attachment: Option<ReceivedAttachment>,
+}
+
+#[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+impl PublishedMessage {
+    /// Message date time
+    #[inline]
+    pub fn created_at(&self) -> SystemTime {
+        util::unix_time(self.time)
+    }
+
+    /// Date time when the message will be deleted
+    #[inline]
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires.map(util::unix_time)
+    }
 }
 
 /// JSON received payload
 ///
+/// Base64-encoded message bodies are decoded when valid UTF-8, otherwise see [`ReceivedPayload::message_bytes`].
+///
 /// <https://docs.ntfy.sh/subscribe/api/#json-message-format>
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(remote = "Self")]
 #[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
 pub struct ReceivedPayload {
     /// Randomly chosen message identifier.
     pub id: String,
     /// Message date time, as Unix time stamp.
-    pub time: u32,
+    pub time: u64,
     /// Unix time stamp indicating when the message will be deleted,
     /// not set if Cache: no is sent
-    pub expires: Option<u32>,
+    pub expires: Option<u64>,
     /// Message type, typically you'd be only interested in message
     pub event: ReceivedMessageType,
     /// Comma-separated list of topics the message is associated with;
@@ -216,7 +393,7 @@ pub struct ReceivedPayload {
     pub sequence_id: Option<String>,
     /// Message body; always present in message events
     pub message: Option<String>,
-    /// Message title; if not set defaults to ntfy.sh/<topic>
+    /// Message title; if not set defaults to `ntfy.sh/<topic>`
     pub title: Option<String>,
     /// List of tags that may or not map to emojis
     pub tags: Option<Vec<String>>,
@@ -228,4 +405,128 @@ pub struct ReceivedPayload {
     pub actions: Option<Vec<Action>>,
     /// Details about an attachment (name, URL, size, ...)
     pub attachment: Option<ReceivedAttachment>,
+    /// URL of the notification icon
+    pub icon: Option<Url>,
+    /// Content type of the message body (i.e. `text/markdown`)
+    pub content_type: Option<String>,
+    /// Encoding of the message body: `base64` for binary messages, otherwise not set
+    pub encoding: Option<String>,
+    /// Fields not known by this library
+    #[serde(flatten)]
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+impl<'de> serde::Deserialize<'de> for ReceivedPayload {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let mut payload: Self = Self::deserialize(deserializer)?;
+
+        // Decode the base64 body, if it's valid UTF-8
+        if let (Some("base64"), Some(message)) = (payload.encoding.as_deref(), &payload.message) {
+            if let Some(decoded) = decode_base64(message).and_then(|b| String::from_utf8(b).ok()) {
+                payload.message = Some(decoded);
+                payload.encoding = None;
+            }
+        }
+
+        Ok(payload)
+    }
+}
+
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+impl ReceivedPayload {
+    /// Topics the message is associated with
+    ///
+    /// Message events always have a single topic, while open events
+    /// list all the subscribed topics.
+    #[inline]
+    pub fn topics(&self) -> impl Iterator<Item = &str> {
+        self.topic.split(',')
+    }
+
+    /// Message date time
+    #[inline]
+    pub fn created_at(&self) -> SystemTime {
+        util::unix_time(self.time)
+    }
+
+    /// Date time when the message will be deleted
+    #[inline]
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires.map(util::unix_time)
+    }
+
+    /// Check if the message body is markdown
+    #[inline]
+    pub fn is_markdown(&self) -> bool {
+        self.content_type.as_deref() == Some("text/markdown")
+    }
+
+    /// Raw message body, decoding binary (base64) messages
+    ///
+    /// Returns `None` if there is no message or the base64 is invalid.
+    pub fn message_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        message_bytes(self.message.as_deref()?, self.encoding.as_deref())
+    }
+}
+
+/// Raw message body, decoding binary (base64) messages
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+pub(crate) fn message_bytes<'a>(message: &'a str, encoding: Option<&str>) -> Option<Cow<'a, [u8]>> {
+    match encoding {
+        Some("base64") => decode_base64(message).map(Cow::Owned),
+        _ => Some(Cow::Borrowed(message.as_bytes())),
+    }
+}
+
+#[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    general_purpose::STANDARD.decode(data).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    #[allow(unused_imports)]
+    use super::*;
+
+    #[test]
+    #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+    fn test_deserialize_published_message() {
+        let json = r#"{"id":"sPs71M8A2T","time":1643935928,"expires":1643979128,"event":"message","topic":"mytopic","message":"Backup successful"}"#;
+        let message: PublishedMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(message.id, "sPs71M8A2T");
+        assert_eq!(message.time, 1643935928);
+        assert_eq!(message.expires, Some(1643979128));
+        assert_eq!(message.topic, "mytopic");
+        assert_eq!(message.sequence_id, None);
+        assert_eq!(message.attachment, None);
+    }
+
+    #[test]
+    #[cfg(any(feature = "async-subscriber", feature = "blocking-subscriber"))]
+    fn test_deserialize_received_payload() {
+        let json = r#"{"id":"sPs71M8A2T","time":1643935928,"expires":1643979128,"event":"message","topic":"mytopic","message":"SGVsbG8=","encoding":"base64","content_type":"text/markdown","icon":"https://example.com/icon.png","attachment":{"name":"backup.zip","type":"application/zip","size":5000000000,"expires":1643946728,"url":"https://ntfy.sh/file/sPs71M8A2T.zip"},"new_field":true}"#;
+        let payload: ReceivedPayload = serde_json::from_str(json).unwrap();
+        assert_eq!(payload.message.as_deref(), Some("Hello"));
+        assert_eq!(payload.encoding, None);
+        assert!(payload.is_markdown());
+        assert_eq!(
+            payload.created_at(),
+            SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1643935928)
+        );
+        assert_eq!(payload.extra["new_field"], serde_json::Value::Bool(true));
+
+        let attachment = payload.attachment.unwrap();
+        assert_eq!(attachment.mime_type.as_deref(), Some("application/zip"));
+        assert_eq!(attachment.size, Some(5000000000));
+
+        // Binary message
+        let json = r#"{"id":"sPs71M8A2T","time":1643935928,"event":"message","topic":"mytopic","message":"/wA=","encoding":"base64"}"#;
+        let payload: ReceivedPayload = serde_json::from_str(json).unwrap();
+        assert_eq!(payload.encoding.as_deref(), Some("base64"));
+        assert_eq!(payload.message_bytes().unwrap().as_ref(), &[0xff, 0x00]);
+    }
 }
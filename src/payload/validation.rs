@@ -0,0 +1,283 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::fmt;
+
+use super::{MAX_ACTIONS, Payload};
+use crate::error::Error;
+use crate::util;
+
+/// Max message body size, in bytes (default `message-size-limit` of the server)
+pub const MAX_MESSAGE_LEN: usize = 4096;
+
+/// Payload field not matching the ntfy server limits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// Topic name doesn't match `[-_A-Za-z0-9]{1,64}`
+    InvalidTopic(String),
+    /// Message body longer than the max length
+    MessageTooLong { len: usize, max: usize },
+    /// Title longer than the max length
+    TitleTooLong { len: usize, max: usize },
+    /// Tag empty, longer than the max length or, when sent as header, containing a comma
+    InvalidTag(String),
+    /// More actions than [`MAX_ACTIONS`]
+    TooManyActions(usize),
+    /// Field sent as header containing control characters (i.e. new lines)
+    InvalidHeaderValue(&'static str),
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidTopic(topic) => write!(f, "invalid topic: {}", topic),
+            Self::MessageTooLong { len, max } => {
+                write!(f, "message too long: {} bytes (max {})", len, max)
+            }
+            Self::TitleTooLong { len, max } => {
+                write!(f, "title too long: {} bytes (max {})", len, max)
+            }
+            Self::InvalidTag(tag) => write!(f, "invalid tag: {}", tag),
+            Self::TooManyActions(count) => {
+                write!(f, "too many actions: {} (max {})", count, MAX_ACTIONS)
+            }
+            Self::InvalidHeaderValue(field) => {
+                write!(f, "{} contains control characters", field)
+            }
+        }
+    }
+}
+
+/// Limits checked before sending
+///
+/// The defaults match the default config of the server:
+/// raise them for self-hosted servers with a larger `message-size-limit`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationPolicy {
+    max_message_len: Option<usize>,
+    max_title_len: Option<usize>,
+    max_tag_len: Option<usize>,
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        Self {
+            max_message_len: Some(MAX_MESSAGE_LEN),
+            max_title_len: None,
+            max_tag_len: None,
+        }
+    }
+}
+
+impl ValidationPolicy {
+    /// New default policy
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Max message body size, in bytes (default: [`MAX_MESSAGE_LEN`])
+    ///
+    /// Not checked when the message is sent as body, since the server turns the large ones into attachments.
+    /// `None` disables the check.
+    #[inline]
+    pub fn max_message_len(mut self, len: Option<usize>) -> Self {
+        self.max_message_len = len;
+        self
+    }
+
+    /// Max title length, in bytes (default: no limit)
+    #[inline]
+    pub fn max_title_len(mut self, len: Option<usize>) -> Self {
+        self.max_title_len = len;
+        self
+    }
+
+    /// Max tag length, in bytes (default: no limit)
+    #[inline]
+    pub fn max_tag_len(mut self, len: Option<usize>) -> Self {
+        self.max_tag_len = len;
+        self
+    }
+
+    /// Configured max message body size
+    #[inline]
+    #[cfg(any(feature = "async-dispatcher", feature = "blocking-dispatcher"))]
+    pub(crate) fn message_limit(&self) -> Option<usize> {
+        self.max_message_len
+    }
+
+    /// Check the payload, sent as described by the [`Encoding`]
+    pub(crate) fn check(&self, payload: &Payload, encoding: Encoding) -> Result<(), Error> {
+        let violations: Vec<Violation> = self.violations(payload, encoding);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidPayload(violations))
+        }
+    }
+
+    /// Collect all the violations of the payload
+    fn violations(&self, payload: &Payload, encoding: Encoding) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = Vec::new();
+
+        if util::validate_topic(&payload.topic).is_err() {
+            violations.push(Violation::InvalidTopic(payload.topic.clone()));
+        }
+
+        if let (Some(message), Some(max), false) = (
+            &payload.message,
+            self.max_message_len,
+            encoding == Encoding::Headers,
+        ) {
+            if message.len() > max {
+                violations.push(Violation::MessageTooLong {
+                    len: message.len(),
+                    max,
+                });
+            }
+        }
+
+        if let (Some(title), Some(max)) = (&payload.title, self.max_title_len) {
+            if title.len() > max {
+                violations.push(Violation::TitleTooLong {
+                    len: title.len(),
+                    max,
+                });
+            }
+        }
+
+        let headers: bool = encoding != Encoding::Json;
+
+        if let Some(tags) = &payload.tags {
+            for tag in tags.iter() {
+                if tag.is_empty()
+                    || self.max_tag_len.is_some_and(|max| tag.len() > max)
+                    || (headers && tag.contains(','))
+                {
+                    violations.push(Violation::InvalidTag(tag.clone()));
+                }
+            }
+        }
+
+        if let Some(actions) = &payload.actions {
+            if actions.len() > MAX_ACTIONS {
+                violations.push(Violation::TooManyActions(actions.len()));
+            }
+        }
+
+        // Fields sent as headers: the header-only ones are in every encoding
+        let mut fields = vec![
+            ("poll_id", payload.poll_id.as_deref()),
+            ("template", payload.template.as_deref()),
+        ];
+        if headers {
+            fields.extend([
+                ("title", payload.title.as_deref()),
+                ("filename", payload.filename.as_deref()),
+                ("delay", payload.delay.as_deref()),
+                ("email", payload.email.as_deref()),
+                ("sequence_id", payload.sequence_id.as_deref()),
+            ]);
+        }
+        for (field, value) in fields.into_iter() {
+            if value.is_some_and(|v| v.chars().any(char::is_control)) {
+                violations.push(Violation::InvalidHeaderValue(field));
+            }
+        }
+
+        if let (Some(tags), true) = (&payload.tags, headers) {
+            if tags.iter().any(|t| t.chars().any(char::is_control)) {
+                violations.push(Violation::InvalidHeaderValue("tags"));
+            }
+        }
+
+        violations
+    }
+}
+
+/// How the payload fields are sent to the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    /// JSON body, with the header-only fields as headers
+    Json,
+    /// Message as body, with the other fields as headers
+    Headers,
+    /// Both, checking all the rules
+    Any,
+}
+
+#[cfg(test)]
+mod tests {
+    use url::Url;
+
+    use super::*;
+    use crate::payload::Action;
+
+    #[test]
+    fn test_violations() {
+        let policy = ValidationPolicy::default();
+        let payload = Payload::new("mytopic")
+            .message("Hello")
+            .title("Title")
+            .tags(["warning"]);
+        assert!(policy.violations(&payload, Encoding::Any).is_empty());
+
+        let url = Url::parse("https://ntfy.sh").unwrap();
+        let actions = (0..4).map(|i| Action::view(format!("Open {i}"), url.clone()));
+        let payload = Payload::new("my topic")
+            .message("a".repeat(MAX_MESSAGE_LEN + 1))
+            .title("Line 1\nLine 2")
+            .tags(["ok", "a,b", ""])
+            .actions(actions);
+        assert_eq!(
+            policy.violations(&payload, Encoding::Any),
+            vec![
+                Violation::InvalidTopic(String::from("my topic")),
+                Violation::MessageTooLong {
+                    len: MAX_MESSAGE_LEN + 1,
+                    max: MAX_MESSAGE_LEN
+                },
+                Violation::InvalidTag(String::from("a,b")),
+                Violation::InvalidTag(String::new()),
+                Violation::TooManyActions(4),
+                Violation::InvalidHeaderValue("title"),
+            ]
+        );
+
+        // Nothing but the header-only fields is sent as header
+        assert_eq!(
+            policy.violations(&payload, Encoding::Json),
+            vec![
+                Violation::InvalidTopic(String::from("my topic")),
+                Violation::MessageTooLong {
+                    len: MAX_MESSAGE_LEN + 1,
+                    max: MAX_MESSAGE_LEN
+                },
+                Violation::InvalidTag(String::new()),
+                Violation::TooManyActions(4),
+            ]
+        );
+
+        // The server turns the large bodies into attachments
+        let payload = Payload::new("mytopic").message("a".repeat(MAX_MESSAGE_LEN + 1));
+        assert!(policy.violations(&payload, Encoding::Headers).is_empty());
+        assert!(!policy.violations(&payload, Encoding::Json).is_empty());
+
+        // Custom limits
+        let payload = payload.title("Title").tags(["warning"]);
+        let policy = ValidationPolicy::new()
+            .max_message_len(Some(8192))
+            .max_title_len(Some(3))
+            .max_tag_len(Some(4));
+        assert_eq!(
+            policy.violations(&payload, Encoding::Json),
+            vec![
+                Violation::TitleTooLong { len: 5, max: 3 },
+                Violation::InvalidTag(String::from("warning")),
+            ]
+        );
+        let policy = policy.max_title_len(None).max_tag_len(None);
+        assert!(policy.violations(&payload, Encoding::Any).is_empty());
+    }
+}
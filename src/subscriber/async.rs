@@ -1,70 +1,565 @@
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+use std::fmt;
+use std::future::Future;
+#[cfg(feature = "socks")]
+use std::io;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
-use futures_util::stream::{FusedStream, Stream, StreamExt};
+use futures_util::future;
+use futures_util::sink::SinkExt;
+use futures_util::stream::{self, FusedStream, Stream, StreamExt};
+#[cfg(feature = "socks")]
+use reqwest::Proxy;
+use reqwest::{Client, ClientBuilder, Response};
 use tokio::net::TcpStream;
-use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+use tokio::time::{Instant, Sleep};
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+use tokio_tungstenite::Connector;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 use tungstenite::protocol::Message;
 use url::Url;
 
 use super::builder::SubscriberBuilder;
-use super::request::get_request_builder;
+use super::event::{self, Event};
+use super::filter::SubscriptionFilter;
+use super::reconnect::{self, Cursor, ReconnectPolicy};
+use super::since::Since;
+use super::transport::Transport;
+use super::watchdog::Watchdog;
+use super::{net, request};
 use crate::auth::Auth;
 use crate::error::Error;
 use crate::payload::ReceivedPayload;
 
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// WebSocket TLS connector
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+#[derive(Clone)]
+struct TlsConnector(Connector);
+
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConnector").finish_non_exhaustive()
+    }
+}
+
 /// Async subscriber
 #[derive(Debug, Clone)]
 pub struct Async {
     auth: Option<Auth>,
+    filter: SubscriptionFilter,
+    transport: Transport,
+    client: Client,
+    #[cfg(feature = "socks")]
+    proxy: Option<Url>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    keepalive_timeout: Option<Duration>,
+    ping_interval: Option<Duration>,
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    connector: Option<TlsConnector>,
 }
 
 impl Async {
     #[inline]
     pub(crate) fn new(builder: SubscriberBuilder) -> Result<Self, Error> {
-        Ok(Self { auth: builder.auth })
+        Self::new_with_client(builder, ClientBuilder::new())
+    }
+
+    pub(crate) fn new_with_client(
+        builder: SubscriberBuilder,
+        mut client: ClientBuilder,
+    ) -> Result<Self, Error> {
+        #[cfg(feature = "socks")]
+        let proxy: Option<Url> = match builder.proxy {
+            Some(proxy) => {
+                client = client.proxy(Proxy::all(&proxy)?);
+                Some(Url::parse(&proxy)?)
+            }
+            None => None,
+        };
+
+        if let Some(timeout) = builder.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+
+        if let Some(timeout) = builder.read_timeout {
+            client = client.read_timeout(timeout);
+        }
+
+        Ok(Self {
+            auth: builder.auth,
+            filter: builder.filter,
+            transport: builder.transport,
+            client: client.build()?,
+            #[cfg(feature = "socks")]
+            proxy,
+            connect_timeout: builder.connect_timeout,
+            read_timeout: builder.read_timeout,
+            keepalive_timeout: builder.keepalive_timeout,
+            ping_interval: builder.ping_interval,
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            connector: None,
+        })
+    }
+
+    /// Use a custom TLS connector for the WebSocket transport
+    #[inline]
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub(crate) fn with_connector(mut self, connector: Connector) -> Self {
+        self.connector = Some(TlsConnector(connector));
+        self
     }
 
     pub(crate) async fn subscribe(&self, url: &Url, topic: &str) -> Result<MessageStream, Error> {
-        let builder = get_request_builder(url, topic, &self.auth)?;
+        self.connect(url, topic, None).await
+    }
+
+    pub(crate) async fn subscribe_with_reconnect(
+        &self,
+        url: &Url,
+        topic: &str,
+        policy: ReconnectPolicy,
+    ) -> Result<ReconnectingMessageStream, Error> {
+        let state = ReconnectState {
+            stream: Some(self.connect(url, topic, None).await?),
+            subscriber: self.clone(),
+            url: url.clone(),
+            topic: topic.to_string(),
+            policy,
+            cursor: Cursor::default(),
+            attempts: 0,
+            done: false,
+        };
 
-        // Create message iterator
-        Ok(MessageStream {
-            socket: connect_async(builder).await?.0,
+        Ok(ReconnectingMessageStream {
+            inner: Box::pin(stream::unfold(state, ReconnectState::next)),
         })
     }
+
+    pub(crate) async fn poll(
+        &self,
+        url: &Url,
+        topic: &str,
+        since: &Since,
+        scheduled: bool,
+    ) -> Result<Vec<ReceivedPayload>, Error> {
+        let mut url: Url = request::topic_url(
+            &request::http_url(url),
+            topic,
+            "json",
+            Some(since),
+            &self.filter,
+        )?;
+        url.query_pairs_mut().append_pair("poll", "1");
+        if scheduled {
+            url.query_pairs_mut().append_pair("scheduled", "1");
+        }
+
+        // Build request
+        let mut builder = self.client.get(url);
+        if let Some(auth) = &self.auth {
+            builder = builder.header("Authorization", auth.header_value());
+        }
+
+        // Send request
+        let res: Response = builder.send().await?;
+        let status = res.status();
+        let text: String = res.text().await?;
+
+        if !status.is_success() {
+            return Err(Error::server(status.as_u16(), text.as_bytes()));
+        }
+
+        request::parse_json_lines(&text)
+    }
+
+    async fn connect(
+        &self,
+        url: &Url,
+        topic: &str,
+        since: Option<&Since>,
+    ) -> Result<MessageStream, Error> {
+        match self.transport {
+            Transport::WebSocket => {
+                let url: Url = request::topic_url(url, topic, "ws", since, &self.filter)?;
+
+                let socket: WebSocket = match self.connect_timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, self.connect_websocket(&url))
+                        .await
+                        .map_err(|_| Error::Timeout)??,
+                    None => self.connect_websocket(&url).await?,
+                };
+
+                // Create message iterator
+                Ok(MessageStream::new(
+                    StreamInner::WebSocket(socket),
+                    Watchdog::new(
+                        self.read_timeout,
+                        self.keepalive_timeout,
+                        self.ping_interval,
+                    ),
+                ))
+            }
+            Transport::Json | Transport::Sse => {
+                let url: Url = request::topic_url(
+                    &request::http_url(url),
+                    topic,
+                    self.transport.endpoint(),
+                    since,
+                    &self.filter,
+                )?;
+
+                // Build request
+                let mut builder = self.client.get(url);
+                if let Some(auth) = &self.auth {
+                    builder = builder.header("Authorization", auth.header_value());
+                }
+
+                // Send request
+                let res: Response = builder.send().await?;
+                let status = res.status();
+
+                if !status.is_success() {
+                    let text: String = res.text().await?;
+                    return Err(Error::server(status.as_u16(), text.as_bytes()));
+                }
+
+                // Create message iterator
+                let body = res.bytes_stream().map(|chunk| chunk.map(|b| b.to_vec()));
+                Ok(MessageStream::new(
+                    StreamInner::Http {
+                        body: Box::pin(body.fuse()),
+                        buffer: Vec::new(),
+                        transport: self.transport,
+                    },
+                    Watchdog::new(self.read_timeout, self.keepalive_timeout, None),
+                ))
+            }
+        }
+    }
+
+    async fn connect_websocket(&self, url: &Url) -> Result<WebSocket, Error> {
+        let (host, port) = net::host_and_port(url)?;
+        let request = request::get_request_builder(url, &self.auth);
+
+        let stream: TcpStream = self.connect_tcp(host, port).await?;
+        stream.set_nodelay(true)?;
+
+        #[cfg(any(feature = "rustls", feature = "native-tls"))]
+        let (socket, _) = tokio_tungstenite::client_async_tls_with_config(
+            request,
+            stream,
+            None,
+            self.connector.clone().map(|c| c.0),
+        )
+        .await?;
+
+        #[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+        let (socket, _) = {
+            net::check_tls(url)?;
+            tokio_tungstenite::client_async_with_config(
+                request,
+                MaybeTlsStream::Plain(stream),
+                None,
+            )
+            .await?
+        };
+
+        Ok(socket)
+    }
+
+    async fn connect_tcp(&self, host: String, port: u16) -> Result<TcpStream, Error> {
+        #[cfg(feature = "socks")]
+        if let Some(proxy) = self.proxy.clone() {
+            // The proxy handshake is blocking
+            let timeout: Option<Duration> = self.connect_timeout;
+            let stream = tokio::task::spawn_blocking(move || {
+                net::connect_via_proxy(&proxy, &host, port, timeout)
+            })
+            .await
+            .map_err(|e| Error::Io(io::Error::other(e)))??;
+            stream.set_nonblocking(true)?;
+            return Ok(TcpStream::from_std(stream)?);
+        }
+
+        Ok(TcpStream::connect((host.as_str(), port)).await?)
+    }
+}
+
+type BodyStream = Pin<Box<dyn Stream<Item = Result<Vec<u8>, reqwest::Error>> + Send>>;
+
+enum StreamInner {
+    WebSocket(WebSocket),
+    Http {
+        body: BodyStream,
+        /// Bytes received after the last complete line
+        buffer: Vec<u8>,
+        transport: Transport,
+    },
 }
 
 pub struct MessageStream {
-    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    inner: StreamInner,
+    watchdog: Watchdog,
+    /// Timer of the next watchdog check, if enabled
+    timer: Option<Pin<Box<Sleep>>>,
+    timed_out: bool,
+}
+
+impl MessageStream {
+    fn new(inner: StreamInner, watchdog: Watchdog) -> Self {
+        let timer = watchdog
+            .deadline()
+            .map(|deadline| Box::pin(tokio::time::sleep_until(Instant::from_std(deadline))));
+        Self {
+            inner,
+            watchdog,
+            timer,
+            timed_out: false,
+        }
+    }
+
+    /// Time of the last received event, `keepalive` included
+    #[inline]
+    pub fn last_event(&self) -> std::time::Instant {
+        self.watchdog.last_event()
+    }
+
+    /// Convert to a stream of typed [`Event`]
+    #[inline]
+    pub fn events(self) -> impl Stream<Item = Result<Event, Error>> + Send + Unpin {
+        self.map(event::event)
+    }
+
+    /// Convert to a stream of the [`Message`](event::Message) events only
+    #[inline]
+    pub fn messages(self) -> impl Stream<Item = Result<event::Message, Error>> + Send + Unpin {
+        self.filter_map(|item| future::ready(event::message(item)))
+    }
+
+    /// Poll the watchdog timer, sending the pings
+    fn poll_watchdog(&mut self, cx: &mut Context<'_>) -> Result<(), Error> {
+        let Some(timer) = &mut self.timer else {
+            return Ok(());
+        };
+
+        while timer.as_mut().poll(cx).is_ready() {
+            let now = std::time::Instant::now();
+            self.watchdog.check(now)?;
+
+            if self.watchdog.ping(now) {
+                if let StreamInner::WebSocket(socket) = &mut self.inner {
+                    send_ping(socket, cx);
+                }
+            }
+
+            if let Some(deadline) = self.watchdog.deadline() {
+                timer.as_mut().reset(Instant::from_std(deadline));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Stream for MessageStream {
     type Item = Result<ReceivedPayload, Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if self.socket.is_terminated() {
+        let this = &mut *self;
+
+        if this.timed_out {
             return Poll::Ready(None);
         }
 
-        let text_message = loop {
-            let message = match self.socket.poll_next_unpin(cx) {
-                Poll::Pending => return Poll::Pending,
-                Poll::Ready(Some(Ok(message))) => message,
-                Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(Error::from(error)))),
-                Poll::Ready(None) => return Poll::Ready(None),
-            };
-
-            match message {
-                Message::Close(_) => return Poll::Ready(None),
-                Message::Text(text_message) => break text_message,
-                _ => {}
+        let poll = match &mut this.inner {
+            StreamInner::WebSocket(socket) => poll_websocket(socket, &mut this.watchdog, cx),
+            StreamInner::Http {
+                body,
+                buffer,
+                transport,
+            } => poll_http(body, buffer, transport, &mut this.watchdog, cx),
+        };
+
+        match poll {
+            Poll::Ready(item) => {
+                if let Some(Ok(..)) = &item {
+                    this.watchdog.event();
+                }
+                Poll::Ready(item)
             }
+            Poll::Pending => match this.poll_watchdog(cx) {
+                Ok(()) => Poll::Pending,
+                Err(e) => {
+                    // The connection is considered dead
+                    this.timed_out = true;
+                    Poll::Ready(Some(Err(e)))
+                }
+            },
+        }
+    }
+}
+
+/// Best effort: a broken connection is detected by the next read
+fn send_ping(socket: &mut WebSocket, cx: &mut Context<'_>) {
+    if let Poll::Ready(Ok(())) = socket.poll_ready_unpin(cx) {
+        let _ = socket.start_send_unpin(Message::Ping(Default::default()));
+        let _ = socket.poll_flush_unpin(cx);
+    }
+}
+
+fn poll_websocket(
+    socket: &mut WebSocket,
+    watchdog: &mut Watchdog,
+    cx: &mut Context<'_>,
+) -> Poll<Option<Result<ReceivedPayload, Error>>> {
+    if socket.is_terminated() {
+        return Poll::Ready(None);
+    }
+
+    let text_message = loop {
+        let message = match socket.poll_next_unpin(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Some(Ok(message))) => message,
+            Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(Error::from(error)))),
+            Poll::Ready(None) => return Poll::Ready(None),
         };
 
-        match serde_json::from_str(text_message.as_str()) {
-            Ok(received_message) => Poll::Ready(Some(Ok(received_message))),
-            Err(error) => Poll::Ready(Some(Err(Error::from(error)))),
+        watchdog.read();
+
+        match message {
+            Message::Close(_) => return Poll::Ready(None),
+            Message::Text(text_message) => break text_message,
+            _ => {}
         }
+    };
+
+    match serde_json::from_str(text_message.as_str()) {
+        Ok(received_message) => Poll::Ready(Some(Ok(received_message))),
+        Err(error) => Poll::Ready(Some(Err(Error::from(error)))),
+    }
+}
+
+fn poll_http(
+    body: &mut BodyStream,
+    buffer: &mut Vec<u8>,
+    transport: &Transport,
+    watchdog: &mut Watchdog,
+    cx: &mut Context<'_>,
+) -> Poll<Option<Result<ReceivedPayload, Error>>> {
+    loop {
+        // Decode the complete lines
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            if let Some(item) = transport.decode_line(&line) {
+                return Poll::Ready(Some(item));
+            }
+        }
+
+        match body.poll_next_unpin(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Some(Ok(chunk))) => {
+                watchdog.read();
+                buffer.extend(chunk);
+            }
+            Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(Error::from(error)))),
+            Poll::Ready(None) => return Poll::Ready(None),
+        }
+    }
+}
+
+struct ReconnectState {
+    subscriber: Async,
+    url: Url,
+    topic: String,
+    policy: ReconnectPolicy,
+    stream: Option<MessageStream>,
+    cursor: Cursor,
+    /// Consecutive reconnection attempts without receiving any event
+    attempts: u32,
+    done: bool,
+}
+
+impl ReconnectState {
+    async fn next(mut self) -> Option<(Result<ReceivedPayload, Error>, Self)> {
+        while !self.done {
+            match &mut self.stream {
+                Some(stream) => match stream.next().await {
+                    Some(Ok(payload)) => {
+                        self.cursor.update(&payload);
+                        self.attempts = 0;
+                        return Some((Ok(payload), self));
+                    }
+                    // Invalid message: the connection is still alive
+                    Some(Err(e @ Error::Serde(..))) => return Some((Err(e), self)),
+                    Some(Err(e)) => {
+                        self.stream = None;
+                        return Some((Err(e), self));
+                    }
+                    None => self.stream = None,
+                },
+                None => {
+                    self.attempts += 1;
+
+                    if !self.policy.can_retry(self.attempts) {
+                        return None;
+                    }
+
+                    tokio::time::sleep(self.policy.backoff_for(self.attempts)).await;
+
+                    // Resume from the last received message
+                    let since: Option<Since> = self.cursor.since();
+                    match self
+                        .subscriber
+                        .connect(&self.url, &self.topic, since.as_ref())
+                        .await
+                    {
+                        Ok(stream) => self.stream = Some(stream),
+                        Err(e) => {
+                            self.done = !reconnect::is_recoverable(&e);
+                            return Some((Err(e), self));
+                        }
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Message stream that automatically reconnects, resuming from the last received message
+///
+/// Connection errors are yielded as items, while the stream keeps reconnecting
+/// according to the [`ReconnectPolicy`].
+pub struct ReconnectingMessageStream {
+    inner: Pin<Box<dyn Stream<Item = Result<ReceivedPayload, Error>> + Send>>,
+}
+
+impl ReconnectingMessageStream {
+    /// Convert to a stream of typed [`Event`]
+    #[inline]
+    pub fn events(self) -> impl Stream<Item = Result<Event, Error>> + Send + Unpin {
+        self.map(event::event)
+    }
+
+    /// Convert to a stream of the [`Message`](event::Message) events only
+    #[inline]
+    pub fn messages(self) -> impl Stream<Item = Result<event::Message, Error>> + Send + Unpin {
+        self.filter_map(|item| future::ready(event::message(item)))
+    }
+}
+
+impl Stream for ReconnectingMessageStream {
+    type Item = Result<ReceivedPayload, Error>;
+
+    #[inline]
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
     }
 }
@@ -1,46 +1,388 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Read};
 use std::net::TcpStream;
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use std::{fmt, thread};
 
-use tungstenite::client::connect;
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+use tungstenite::Connector;
+use tungstenite::handshake::HandshakeError;
 use tungstenite::protocol::{Message, WebSocket};
 use tungstenite::stream::MaybeTlsStream;
+#[cfg(feature = "socks")]
+use ureq::Proxy;
+use ureq::config::ConfigBuilder;
+use ureq::http::Response;
+use ureq::typestate::AgentScope;
+use ureq::{Agent, Body, BodyReader};
 use url::Url;
 
 use super::builder::SubscriberBuilder;
-use super::request::get_request_builder;
+use super::event::{self, Event};
+use super::filter::SubscriptionFilter;
+use super::reconnect::{self, Cursor, ReconnectPolicy};
+use super::since::Since;
+use super::transport::Transport;
+use super::watchdog::Watchdog;
+use super::{net, request};
 use crate::auth::Auth;
 use crate::error::Error;
 use crate::payload::ReceivedPayload;
 
+/// WebSocket TLS connector
+///
+/// `Connector` doesn't implement `Clone`: keep a way to rebuild it for each connection instead.
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+#[derive(Clone)]
+struct TlsConnector(Arc<dyn Fn() -> Connector + Send + Sync>);
+
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+impl TlsConnector {
+    fn new(connector: Connector) -> Result<Self, Error> {
+        let make: Arc<dyn Fn() -> Connector + Send + Sync> = match connector {
+            Connector::Plain => Arc::new(|| Connector::Plain),
+            #[cfg(feature = "native-tls")]
+            Connector::NativeTls(connector) => {
+                Arc::new(move || Connector::NativeTls(connector.clone()))
+            }
+            #[cfg(feature = "rustls")]
+            Connector::Rustls(config) => Arc::new(move || Connector::Rustls(config.clone())),
+            // `Connector` is non-exhaustive: reject the variants that can't be rebuilt
+            _ => return Err(Error::UnsupportedConnector),
+        };
+        Ok(Self(make))
+    }
+
+    #[inline]
+    fn connector(&self) -> Connector {
+        (self.0)()
+    }
+}
+
+#[cfg(any(feature = "rustls", feature = "native-tls"))]
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConnector").finish_non_exhaustive()
+    }
+}
+
 /// Blocking subscriber
 #[derive(Debug, Clone)]
 pub struct Blocking {
     auth: Option<Auth>,
+    filter: SubscriptionFilter,
+    transport: Transport,
+    client: Agent,
+    #[cfg(feature = "socks")]
+    proxy: Option<Url>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    keepalive_timeout: Option<Duration>,
+    ping_interval: Option<Duration>,
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    connector: Option<TlsConnector>,
 }
 
 impl Blocking {
     #[inline]
     pub(crate) fn new(builder: SubscriberBuilder) -> Result<Self, Error> {
-        Ok(Self { auth: builder.auth })
+        Self::new_with_client(builder, Agent::config_builder())
+    }
+
+    pub(crate) fn new_with_client(
+        builder: SubscriberBuilder,
+        mut client: ConfigBuilder<AgentScope>,
+    ) -> Result<Self, Error> {
+        // Error responses are handled by the subscriber, to parse the body
+        client = client
+            .http_status_as_error(false)
+            .timeout_connect(builder.connect_timeout)
+            .timeout_recv_response(builder.read_timeout);
+
+        #[cfg(feature = "socks")]
+        let proxy: Option<Url> = match builder.proxy {
+            Some(proxy) => {
+                client = client.proxy(Some(Proxy::new(&proxy)?));
+                Some(Url::parse(&proxy)?)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            auth: builder.auth,
+            filter: builder.filter,
+            transport: builder.transport,
+            client: client.build().into(),
+            #[cfg(feature = "socks")]
+            proxy,
+            connect_timeout: builder.connect_timeout,
+            read_timeout: builder.read_timeout,
+            keepalive_timeout: builder.keepalive_timeout,
+            ping_interval: builder.ping_interval,
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            connector: None,
+        })
+    }
+
+    /// Use a custom TLS connector for the WebSocket transport
+    #[inline]
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    pub(crate) fn with_connector(mut self, connector: Connector) -> Result<Self, Error> {
+        self.connector = Some(TlsConnector::new(connector)?);
+        Ok(self)
     }
 
     pub(crate) fn subscribe(&self, url: &Url, topic: &str) -> Result<MessageStream, Error> {
-        let builder = get_request_builder(url, topic, &self.auth)?;
+        self.connect(url, topic, None)
+    }
 
-        // Create message iterator
-        Ok(MessageStream {
-            socket: connect(builder)?.0,
+    pub(crate) fn subscribe_with_reconnect(
+        &self,
+        url: &Url,
+        topic: &str,
+        policy: ReconnectPolicy,
+    ) -> Result<ReconnectingMessageStream, Error> {
+        Ok(ReconnectingMessageStream {
+            stream: Some(self.connect(url, topic, None)?),
+            subscriber: self.clone(),
+            url: url.clone(),
+            topic: topic.to_string(),
+            policy,
+            cursor: Cursor::default(),
+            attempts: 0,
+            done: false,
         })
     }
+
+    pub(crate) fn poll(
+        &self,
+        url: &Url,
+        topic: &str,
+        since: &Since,
+        scheduled: bool,
+    ) -> Result<Vec<ReceivedPayload>, Error> {
+        let mut url: Url = request::topic_url(
+            &request::http_url(url),
+            topic,
+            "json",
+            Some(since),
+            &self.filter,
+        )?;
+        url.query_pairs_mut().append_pair("poll", "1");
+        if scheduled {
+            url.query_pairs_mut().append_pair("scheduled", "1");
+        }
+
+        // Build request
+        let mut builder = self.client.get(url.as_str());
+        if let Some(auth) = &self.auth {
+            builder = builder.header("Authorization", auth.header_value());
+        }
+
+        // Send request
+        let res: Response<Body> = builder.call()?;
+        let status = res.status();
+
+        // Get full response text, without size limit
+        let mut text = String::new();
+        res.into_body().into_reader().read_to_string(&mut text)?;
+
+        if !status.is_success() {
+            return Err(Error::server(status.as_u16(), text.as_bytes()));
+        }
+
+        request::parse_json_lines(&text)
+    }
+
+    fn connect(
+        &self,
+        url: &Url,
+        topic: &str,
+        since: Option<&Since>,
+    ) -> Result<MessageStream, Error> {
+        match self.transport {
+            Transport::WebSocket => {
+                let url: Url = request::topic_url(url, topic, "ws", since, &self.filter)?;
+                let watchdog = Watchdog::new(
+                    self.read_timeout,
+                    self.keepalive_timeout,
+                    self.ping_interval,
+                );
+
+                // Create message iterator
+                Ok(MessageStream {
+                    inner: StreamInner::WebSocket(self.connect_websocket(&url, &watchdog)?),
+                    watchdog,
+                    timed_out: false,
+                })
+            }
+            Transport::Json | Transport::Sse => {
+                let url: Url = request::topic_url(
+                    &request::http_url(url),
+                    topic,
+                    self.transport.endpoint(),
+                    since,
+                    &self.filter,
+                )?;
+
+                // Build request
+                let mut builder = self.client.get(url.as_str());
+                if let Some(auth) = &self.auth {
+                    builder = builder.header("Authorization", auth.header_value());
+                }
+
+                // Send request
+                let res: Response<Body> = builder.call()?;
+                let status = res.status();
+
+                if !status.is_success() {
+                    let text: String = res.into_body().read_to_string()?;
+                    return Err(Error::server(status.as_u16(), text.as_bytes()));
+                }
+
+                // Create message iterator
+                let reader = BufReader::new(res.into_body().into_reader());
+                let watchdog = Watchdog::new(self.read_timeout, self.keepalive_timeout, None);
+                let inner = match watchdog.tick() {
+                    // The body reader can't be interrupted: read it from another thread
+                    Some(..) => StreamInner::WatchedHttp {
+                        lines: spawn_line_reader(reader),
+                        transport: self.transport,
+                    },
+                    None => StreamInner::Http {
+                        reader,
+                        transport: self.transport,
+                    },
+                };
+
+                Ok(MessageStream {
+                    inner,
+                    watchdog,
+                    timed_out: false,
+                })
+            }
+        }
+    }
+
+    fn connect_websocket(
+        &self,
+        url: &Url,
+        watchdog: &Watchdog,
+    ) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, Error> {
+        let (host, port) = net::host_and_port(url)?;
+        let request = request::get_request_builder(url, &self.auth);
+
+        let stream: TcpStream = self.connect_tcp(&host, port)?;
+        stream.set_nodelay(true)?;
+
+        // Timeouts are shared with the cloned handle, used after the handshake
+        let handle: TcpStream = stream.try_clone()?;
+        handle.set_read_timeout(self.connect_timeout)?;
+        handle.set_write_timeout(self.connect_timeout)?;
+
+        #[cfg(any(feature = "rustls", feature = "native-tls"))]
+        let (socket, _) = tungstenite::client_tls_with_config(
+            request,
+            stream,
+            None,
+            self.connector.as_ref().map(TlsConnector::connector),
+        )
+        .map_err(handshake_error)?;
+
+        #[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+        let (socket, _) = {
+            net::check_tls(url)?;
+            tungstenite::client::client_with_config(request, MaybeTlsStream::Plain(stream), None)
+                .map_err(handshake_error)?
+        };
+
+        // Wake up periodically to run the watchdog
+        handle.set_read_timeout(watchdog.tick())?;
+        handle.set_write_timeout(None)?;
+
+        Ok(socket)
+    }
+
+    fn connect_tcp(&self, host: &str, port: u16) -> Result<TcpStream, Error> {
+        #[cfg(feature = "socks")]
+        if let Some(proxy) = &self.proxy {
+            return net::connect_via_proxy(proxy, host, port, self.connect_timeout);
+        }
+
+        Ok(net::connect(host, port, self.connect_timeout)?)
+    }
+}
+
+fn handshake_error<S>(error: HandshakeError<S>) -> Error
+where
+    S: tungstenite::handshake::HandshakeRole,
+{
+    match error {
+        HandshakeError::Failure(e) => Error::from(e),
+        // The socket timed out during the handshake
+        HandshakeError::Interrupted(..) => Error::Timeout,
+    }
+}
+
+enum StreamInner {
+    WebSocket(WebSocket<MaybeTlsStream<TcpStream>>),
+    Http {
+        reader: BufReader<BodyReader<'static>>,
+        transport: Transport,
+    },
+    /// Lines read by another thread, to check the watchdog while waiting
+    WatchedHttp {
+        lines: Receiver<io::Result<Vec<u8>>>,
+        transport: Transport,
+    },
+}
+
+impl fmt::Debug for StreamInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WebSocket(socket) => f.debug_tuple("WebSocket").field(socket).finish(),
+            Self::Http { transport, .. } => f.debug_tuple("Http").field(transport).finish(),
+            Self::WatchedHttp { transport, .. } => {
+                f.debug_tuple("WatchedHttp").field(transport).finish()
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct MessageStream {
-    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    inner: StreamInner,
+    watchdog: Watchdog,
+    timed_out: bool,
+}
+
+impl MessageStream {
+    /// Time of the last received event, `keepalive` included
+    #[inline]
+    pub fn last_event(&self) -> Instant {
+        self.watchdog.last_event()
+    }
+
+    /// Convert to an iterator of typed [`Event`]
+    #[inline]
+    pub fn events(self) -> impl Iterator<Item = Result<Event, Error>> {
+        self.map(event::event)
+    }
+
+    /// Convert to an iterator of the [`Message`](event::Message) events only
+    #[inline]
+    pub fn messages(self) -> impl Iterator<Item = Result<event::Message, Error>> {
+        self.filter_map(event::message)
+    }
 }
 
 impl Drop for MessageStream {
     fn drop(&mut self) {
-        let _ = self.socket.close(None);
+        if let StreamInner::WebSocket(socket) = &mut self.inner {
+            let _ = socket.close(None);
+        }
     }
 }
 
@@ -48,26 +390,280 @@ impl Iterator for MessageStream {
     type Item = Result<ReceivedPayload, Error>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if !self.socket.can_read() {
+        if self.timed_out {
             return None;
         }
 
-        let text_message = loop {
-            let message = match self.socket.read() {
-                Ok(message) => message,
-                Err(error) => return Some(Err(Error::from(error))),
+        let item = match &mut self.inner {
+            StreamInner::WebSocket(socket) => next_websocket(socket, &mut self.watchdog),
+            StreamInner::Http { reader, transport } => next_http(reader, transport),
+            StreamInner::WatchedHttp { lines, transport } => {
+                next_watched_http(lines, transport, &mut self.watchdog)
+            }
+        };
+
+        match &item {
+            Some(Ok(..)) => self.watchdog.event(),
+            // The connection is considered dead
+            Some(Err(Error::Timeout | Error::KeepaliveTimeout)) => self.timed_out = true,
+            _ => {}
+        }
+
+        item
+    }
+}
+
+fn next_websocket(
+    socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+    watchdog: &mut Watchdog,
+) -> Option<Result<ReceivedPayload, Error>> {
+    if !socket.can_read() {
+        return None;
+    }
+
+    let text_message = loop {
+        match socket.read() {
+            Ok(message) => {
+                watchdog.read();
+
+                match message {
+                    Message::Close(_) => return None,
+                    Message::Text(text_message) => break text_message,
+                    _ => {}
+                }
+            }
+            // Read timeout of the socket, used as watchdog tick
+            Err(tungstenite::Error::Io(e))
+                if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(error) => return Some(Err(Error::from(error))),
+        }
+
+        let now = Instant::now();
+
+        if let Err(e) = watchdog.check(now) {
+            return Some(Err(e));
+        }
+
+        if watchdog.ping(now) {
+            if let Err(e) = socket.send(Message::Ping(Default::default())) {
+                return Some(Err(Error::from(e)));
+            }
+        }
+    };
+
+    match serde_json::from_str(text_message.as_str()) {
+        Ok(received_message) => Some(Ok(received_message)),
+        Err(error) => Some(Err(Error::from(error))),
+    }
+}
+
+fn next_http(
+    reader: &mut BufReader<BodyReader<'static>>,
+    transport: &Transport,
+) -> Option<Result<ReceivedPayload, Error>> {
+    let mut line: Vec<u8> = Vec::new();
+
+    loop {
+        line.clear();
+
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => return None,
+            Ok(..) => {
+                if let Some(item) = transport.decode_line(&line) {
+                    return Some(item);
+                }
+            }
+            Err(error) => return Some(Err(Error::from(error))),
+        }
+    }
+}
+
+/// Read the body lines from a new thread
+///
+/// The thread exits at the end of the body, or at the first line read after the stream is dropped.
+fn spawn_line_reader(mut reader: BufReader<BodyReader<'static>>) -> Receiver<io::Result<Vec<u8>>> {
+    let (sender, receiver) = mpsc::sync_channel(1);
+
+    thread::spawn(move || {
+        loop {
+            let mut line: Vec<u8> = Vec::new();
+
+            let res = match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(..) => sender.send(Ok(line)),
+                Err(error) => {
+                    let _ = sender.send(Err(error));
+                    break;
+                }
             };
 
-            match message {
-                Message::Close(_) => return None,
-                Message::Text(text_message) => break text_message,
-                _ => {}
+            if res.is_err() {
+                break;
             }
+        }
+    });
+
+    receiver
+}
+
+fn next_watched_http(
+    lines: &Receiver<io::Result<Vec<u8>>>,
+    transport: &Transport,
+    watchdog: &mut Watchdog,
+) -> Option<Result<ReceivedPayload, Error>> {
+    loop {
+        let line = match watchdog.tick() {
+            Some(tick) => lines.recv_timeout(tick),
+            None => lines.recv().map_err(RecvTimeoutError::from),
         };
 
-        match serde_json::from_str(text_message.as_str()) {
-            Ok(received_message) => Some(Ok(received_message)),
-            Err(error) => Some(Err(Error::from(error))),
+        match line {
+            Ok(Ok(line)) => {
+                watchdog.read();
+
+                if let Some(item) = transport.decode_line(&line) {
+                    return Some(item);
+                }
+            }
+            Ok(Err(error)) => return Some(Err(Error::from(error))),
+            // Watchdog tick
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return None,
         }
+
+        if let Err(e) = watchdog.check(Instant::now()) {
+            return Some(Err(e));
+        }
+    }
+}
+
+/// Message iterator that automatically reconnects, resuming from the last received message
+///
+/// Connection errors are yielded as items, while the iterator keeps reconnecting
+/// according to the [`ReconnectPolicy`].
+#[derive(Debug)]
+pub struct ReconnectingMessageStream {
+    subscriber: Blocking,
+    url: Url,
+    topic: String,
+    policy: ReconnectPolicy,
+    stream: Option<MessageStream>,
+    cursor: Cursor,
+    /// Consecutive reconnection attempts without receiving any event
+    attempts: u32,
+    done: bool,
+}
+
+impl ReconnectingMessageStream {
+    /// Convert to an iterator of typed [`Event`]
+    #[inline]
+    pub fn events(self) -> impl Iterator<Item = Result<Event, Error>> {
+        self.map(event::event)
+    }
+
+    /// Convert to an iterator of the [`Message`](event::Message) events only
+    #[inline]
+    pub fn messages(self) -> impl Iterator<Item = Result<event::Message, Error>> {
+        self.filter_map(event::message)
+    }
+}
+
+impl Iterator for ReconnectingMessageStream {
+    type Item = Result<ReceivedPayload, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match &mut self.stream {
+                Some(stream) => match stream.next() {
+                    Some(Ok(payload)) => {
+                        self.cursor.update(&payload);
+                        self.attempts = 0;
+                        return Some(Ok(payload));
+                    }
+                    // Invalid message: the connection is still alive
+                    Some(Err(e @ Error::Serde(..))) => return Some(Err(e)),
+                    Some(Err(e)) => {
+                        self.stream = None;
+                        return Some(Err(e));
+                    }
+                    None => self.stream = None,
+                },
+                None => {
+                    self.attempts += 1;
+
+                    if !self.policy.can_retry(self.attempts) {
+                        return None;
+                    }
+
+                    thread::sleep(self.policy.backoff_for(self.attempts));
+
+                    // Resume from the last received message
+                    let since: Option<Since> = self.cursor.since();
+                    match self
+                        .subscriber
+                        .connect(&self.url, &self.topic, since.as_ref())
+                    {
+                        Ok(stream) => self.stream = Some(stream),
+                        Err(e) => {
+                            self.done = !reconnect::is_recoverable(&e);
+                            return Some(Err(e));
+                        }
+                    }
+                }
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::payload::ReceivedMessageType;
+
+    #[test]
+    fn test_http_keepalive_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Send a single event, then hang as a half-open connection
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\n\r\n")
+                .unwrap();
+            stream
+                .write_all(
+                    b"{\"id\":\"1\",\"time\":1,\"event\":\"keepalive\",\"topic\":\"mytopic\"}\n",
+                )
+                .unwrap();
+            thread::sleep(Duration::from_secs(1));
+        });
+
+        let builder = SubscriberBuilder::new(format!("http://{addr}"))
+            .transport(Transport::Json)
+            .keepalive_timeout(Duration::from_millis(200));
+        let url = Url::parse(&format!("http://{addr}")).unwrap();
+        let mut stream = Blocking::new(builder)
+            .unwrap()
+            .subscribe(&url, "mytopic")
+            .unwrap();
+
+        let payload = stream.next().unwrap().unwrap();
+        assert_eq!(payload.event, ReceivedMessageType::Keepalive);
+        assert!(matches!(stream.next(), Some(Err(Error::KeepaliveTimeout))));
+        assert!(stream.next().is_none());
+
+        server.join().unwrap();
     }
 }
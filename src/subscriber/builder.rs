@@ -1,9 +1,19 @@
+use std::time::Duration;
+
+#[cfg(feature = "async-subscriber")]
+use reqwest::ClientBuilder;
+#[cfg(feature = "blocking-subscriber")]
+use ureq::config::ConfigBuilder;
+#[cfg(feature = "blocking-subscriber")]
+use ureq::typestate::AgentScope;
 use url::Url;
 
 #[cfg(feature = "async-subscriber")]
 use super::Async;
 #[cfg(feature = "blocking-subscriber")]
 use super::Blocking;
+use super::filter::SubscriptionFilter;
+use super::transport::{SubscribeTransport, Transport};
 use super::{Error, Subscriber};
 use crate::auth::Auth;
 
@@ -11,6 +21,14 @@ use crate::auth::Auth;
 pub struct SubscriberBuilder {
     url: String,
     pub(crate) auth: Option<Auth>,
+    pub(crate) filter: SubscriptionFilter,
+    pub(crate) transport: Transport,
+    #[cfg(feature = "socks")]
+    pub(crate) proxy: Option<String>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) keepalive_timeout: Option<Duration>,
+    pub(crate) ping_interval: Option<Duration>,
 }
 
 impl SubscriberBuilder {
@@ -22,6 +40,14 @@ impl SubscriberBuilder {
         Self {
             url: url.into(),
             auth: None,
+            filter: SubscriptionFilter::default(),
+            transport: Transport::default(),
+            #[cfg(feature = "socks")]
+            proxy: None,
+            connect_timeout: None,
+            read_timeout: None,
+            keepalive_timeout: None,
+            ping_interval: None,
         }
     }
 
@@ -31,29 +57,175 @@ impl SubscriberBuilder {
         self
     }
 
+    /// Only receive the messages matching the [`SubscriptionFilter`]
+    #[inline]
+    pub fn filter(mut self, filter: SubscriptionFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Set subscription transport (default: [`Transport::WebSocket`])
+    #[inline]
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Connect through a proxy (i.e. `socks5://127.0.0.1:9050` or `http://proxy.local:3128`)
+    ///
+    /// Used for both the WebSocket and the HTTP transports.
+    /// Host names are resolved locally with `socks5://`, and by the proxy with `socks5h://`.
+    #[inline]
+    #[cfg(feature = "socks")]
+    pub fn proxy<S>(mut self, proxy: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Max time to wait for the connection to be established, including the handshake (default: none)
+    #[inline]
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Max time to wait for new data from the server before giving up (default: none)
+    ///
+    /// The stream yields [`Error::Timeout`] when elapsed.
+    /// The blocking HTTP transports read the body from another thread when set.
+    #[inline]
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Max time without receiving any event, `keepalive` included, before considering the connection dead (default: none)
+    ///
+    /// The stream yields [`Error::KeepaliveTimeout`] when elapsed, so the reconnecting streams reconnect.
+    /// The ntfy server sends a `keepalive` event every 45 secs by default, so the timeout should be greater than that.
+    /// As with the [`read_timeout`](Self::read_timeout), the blocking HTTP transports read the body from another thread.
+    ///
+    /// <https://docs.ntfy.sh/config/#config-options>
+    #[inline]
+    pub fn keepalive_timeout(mut self, timeout: Duration) -> Self {
+        self.keepalive_timeout = Some(timeout);
+        self
+    }
+
+    /// Send a WebSocket ping when nothing has been received for `interval` (default: none)
+    ///
+    /// Only used by the [`Transport::WebSocket`] transport.
+    #[inline]
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = Some(interval);
+        self
+    }
+
     #[cfg(feature = "async-subscriber")]
     pub fn build_async(self) -> Result<Subscriber<Async>, Error> {
-        let mut url: Url = Url::parse(&self.url)?;
+        let url: Url = self.parse_url()?;
+        Ok(Subscriber {
+            url,
+            inner: Async::new(self)?,
+        })
+    }
 
-        change_scheme(&mut url)?;
+    /// Build async subscriber, using a custom HTTP client for the [`Transport::Json`] and [`Transport::Sse`] transports
+    #[cfg(feature = "async-subscriber")]
+    pub fn build_async_with_client(
+        self,
+        client: ClientBuilder,
+    ) -> Result<Subscriber<Async>, Error> {
+        let url: Url = self.parse_url()?;
+        Ok(Subscriber {
+            url,
+            inner: Async::new_with_client(self, client)?,
+        })
+    }
 
+    /// Build async subscriber, using a custom HTTP client and TLS connector (i.e. with a custom root CA)
+    ///
+    /// The `connector` is used by the [`Transport::WebSocket`] transport.
+    #[cfg(all(
+        feature = "async-subscriber",
+        any(feature = "rustls", feature = "native-tls")
+    ))]
+    pub fn build_async_with_tls(
+        self,
+        client: ClientBuilder,
+        connector: tokio_tungstenite::Connector,
+    ) -> Result<Subscriber<Async>, Error> {
+        let url: Url = self.parse_url()?;
         Ok(Subscriber {
             url,
-            inner: Async::new(self)?,
+            inner: Async::new_with_client(self, client)?.with_connector(connector),
         })
     }
 
     #[cfg(feature = "blocking-subscriber")]
     pub fn build_blocking(self) -> Result<Subscriber<Blocking>, Error> {
-        let mut url: Url = Url::parse(&self.url)?;
+        let url: Url = self.parse_url()?;
+        Ok(Subscriber {
+            url,
+            inner: Blocking::new(self)?,
+        })
+    }
 
-        change_scheme(&mut url)?;
+    /// Build blocking subscriber, using a custom HTTP client for the [`Transport::Json`] and [`Transport::Sse`] transports
+    #[cfg(feature = "blocking-subscriber")]
+    pub fn build_blocking_with_client(
+        self,
+        client: ConfigBuilder<AgentScope>,
+    ) -> Result<Subscriber<Blocking>, Error> {
+        let url: Url = self.parse_url()?;
+        Ok(Subscriber {
+            url,
+            inner: Blocking::new_with_client(self, client)?,
+        })
+    }
 
+    /// Build blocking subscriber, using a custom HTTP client and TLS connector (i.e. with a custom root CA)
+    ///
+    /// The `connector` is used by the [`Transport::WebSocket`] transport.
+    /// Returns [`Error::UnsupportedConnector`] for the variants of a newer `tungstenite` release.
+    #[cfg(all(
+        feature = "blocking-subscriber",
+        any(feature = "rustls", feature = "native-tls")
+    ))]
+    pub fn build_blocking_with_tls(
+        self,
+        client: ConfigBuilder<AgentScope>,
+        connector: tungstenite::Connector,
+    ) -> Result<Subscriber<Blocking>, Error> {
+        let url: Url = self.parse_url()?;
         Ok(Subscriber {
             url,
-            inner: Blocking::new(self)?,
+            inner: Blocking::new_with_client(self, client)?.with_connector(connector)?,
+        })
+    }
+
+    /// Build subscriber with a custom transport (i.e. [`Memory`](crate::memory::Memory) for tests)
+    ///
+    /// Only the URL is used: auth, proxy, filter and timeouts are up to the transport.
+    pub fn build_with_transport<T>(self, transport: T) -> Result<Subscriber<T>, Error>
+    where
+        T: SubscribeTransport,
+    {
+        let url: Url = self.parse_url()?;
+        Ok(Subscriber {
+            url,
+            inner: transport,
         })
     }
+
+    fn parse_url(&self) -> Result<Url, Error> {
+        let mut url: Url = Url::parse(&self.url)?;
+        change_scheme(&mut url)?;
+        Ok(url)
+    }
 }
 
 fn change_scheme(url: &mut Url) -> Result<(), Error> {
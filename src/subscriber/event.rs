@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use url::Url;
+
+use crate::error::Error;
+use crate::payload::{
+    self, Action, Priority, ReceivedAttachment, ReceivedMessageType, ReceivedPayload,
+};
+use crate::util;
+
+/// Subscription event
+///
+/// Typed alternative to [`ReceivedPayload`], see `events` and `messages` of the message streams.
+///
+/// <https://docs.ntfy.sh/subscribe/api/#json-message-format>
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Connection established
+    Open {
+        /// Subscribed topics
+        topics: Vec<String>,
+    },
+    /// Sent periodically by the server to keep the connection alive
+    Keepalive,
+    /// Notification
+    Message(Message),
+    /// Notification deleted
+    MessageDelete {
+        topic: String,
+        /// Sequence ID of the deleted notification
+        sequence_id: String,
+    },
+    /// Notification marked as read and dismissed
+    MessageClear {
+        topic: String,
+        /// Sequence ID of the cleared notification
+        sequence_id: String,
+    },
+    /// Request to poll the topic, used by the iOS app
+    PollRequest,
+}
+
+/// Notification received with a `message` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// Randomly chosen message identifier.
+    pub id: String,
+    /// Message date time, as Unix time stamp.
+    pub time: u64,
+    /// Unix time stamp indicating when the message will be deleted,
+    /// not set if Cache: no is sent
+    pub expires: Option<u64>,
+    /// Topic the message is associated with
+    pub topic: String,
+    /// Sequence ID for updating/deleting notifications
+    pub sequence_id: Option<String>,
+    /// Message body
+    pub message: String,
+    /// Message title; if not set defaults to `ntfy.sh/<topic>`
+    pub title: Option<String>,
+    /// List of tags that may or not map to emojis
+    pub tags: Option<Vec<String>>,
+    /// Message priority with 1=min, 3=default and 5=max
+    pub priority: Option<Priority>,
+    /// Website opened when notification is clicked
+    pub click: Option<Url>,
+    /// Action buttons that can be displayed in the notification
+    pub actions: Option<Vec<Action>>,
+    /// Details about an attachment (name, URL, size, ...)
+    pub attachment: Option<ReceivedAttachment>,
+    /// URL of the notification icon
+    pub icon: Option<Url>,
+    /// Content type of the message body (i.e. `text/markdown`)
+    pub content_type: Option<String>,
+    /// Encoding of the message body: `base64` for binary messages, otherwise not set
+    pub encoding: Option<String>,
+    /// Fields not known by this library
+    pub extra: HashMap<String, serde_json::Value>,
+}
+
+impl Message {
+    /// Message date time
+    #[inline]
+    pub fn created_at(&self) -> SystemTime {
+        util::unix_time(self.time)
+    }
+
+    /// Date time when the message will be deleted
+    #[inline]
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires.map(util::unix_time)
+    }
+
+    /// Check if the message body is markdown
+    #[inline]
+    pub fn is_markdown(&self) -> bool {
+        self.content_type.as_deref() == Some("text/markdown")
+    }
+
+    /// Raw message body, decoding binary (base64) messages
+    ///
+    /// Returns `None` if the base64 is invalid.
+    #[inline]
+    pub fn message_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        payload::message_bytes(&self.message, self.encoding.as_deref())
+    }
+}
+
+impl From<ReceivedPayload> for Event {
+    fn from(payload: ReceivedPayload) -> Self {
+        match payload.event {
+            ReceivedMessageType::Open => Self::Open {
+                topics: payload.topics().map(String::from).collect(),
+            },
+            ReceivedMessageType::Keepalive => Self::Keepalive,
+            ReceivedMessageType::Message => Self::Message(Message {
+                id: payload.id,
+                time: payload.time,
+                expires: payload.expires,
+                topic: payload.topic,
+                sequence_id: payload.sequence_id,
+                message: payload.message.unwrap_or_default(),
+                title: payload.title,
+                tags: payload.tags,
+                priority: payload.priority,
+                click: payload.click,
+                actions: payload.actions,
+                attachment: payload.attachment,
+                icon: payload.icon,
+                content_type: payload.content_type,
+                encoding: payload.encoding,
+                extra: payload.extra,
+            }),
+            // The sequence ID defaults to the message ID
+            ReceivedMessageType::MessageDelete => Self::MessageDelete {
+                topic: payload.topic,
+                sequence_id: payload.sequence_id.unwrap_or(payload.id),
+            },
+            ReceivedMessageType::MessageClear => Self::MessageClear {
+                topic: payload.topic,
+                sequence_id: payload.sequence_id.unwrap_or(payload.id),
+            },
+            ReceivedMessageType::PollRequest => Self::PollRequest,
+        }
+    }
+}
+
+/// Convert the stream item to [`Event`]
+#[inline]
+pub(crate) fn event(item: Result<ReceivedPayload, Error>) -> Result<Event, Error> {
+    item.map(Event::from)
+}
+
+/// Convert the stream item to [`Message`], skipping the other events
+pub(crate) fn message(item: Result<ReceivedPayload, Error>) -> Option<Result<Message, Error>> {
+    match event(item) {
+        Ok(Event::Message(message)) => Some(Ok(message)),
+        Ok(..) => None,
+        Err(e) => Some(Err(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event() {
+        let payload: ReceivedPayload = serde_json::from_str(
+            r#"{"id":"2pfJQj3SVmWr","time":1643935900,"event":"open","topic":"mytopic,alerts"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            Event::from(payload),
+            Event::Open {
+                topics: vec![String::from("mytopic"), String::from("alerts")]
+            }
+        );
+
+        let payload: ReceivedPayload = serde_json::from_str(
+            r#"{"id":"sPs71M8A2T","time":1643935928,"event":"message","topic":"mytopic","message":"Hello"}"#,
+        )
+        .unwrap();
+        match message(Ok(payload)) {
+            Some(Ok(message)) => assert_eq!(message.message, "Hello"),
+            other => panic!("unexpected: {other:?}"),
+        }
+
+        let payload: ReceivedPayload = serde_json::from_str(
+            r#"{"id":"sPs71M8A2T","time":1643935928,"event":"message_delete","topic":"mytopic","sequence_id":"backup"}"#,
+        )
+        .unwrap();
+        assert!(message(Ok(payload.clone())).is_none());
+        assert_eq!(
+            Event::from(payload),
+            Event::MessageDelete {
+                topic: String::from("mytopic"),
+                sequence_id: String::from("backup")
+            }
+        );
+    }
+}
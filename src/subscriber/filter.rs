@@ -0,0 +1,136 @@
+use crate::payload::Priority;
+
+/// Server-side subscription filter
+///
+/// Only the messages matching **all** the set conditions are delivered.
+///
+/// <https://docs.ntfy.sh/subscribe/api/#filter-messages>
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubscriptionFilter {
+    id: Option<String>,
+    message: Option<String>,
+    title: Option<String>,
+    priorities: Vec<Priority>,
+    tags: Vec<String>,
+}
+
+impl SubscriptionFilter {
+    /// New empty filter
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only return the message with this ID
+    #[inline]
+    pub fn id<S>(mut self, id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Only return messages with this exact message body
+    #[inline]
+    pub fn message<S>(mut self, message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Only return messages with this exact title
+    #[inline]
+    pub fn title<S>(mut self, title: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Only return messages with **any** of these priorities
+    pub fn priorities<I>(mut self, priorities: I) -> Self
+    where
+        I: IntoIterator<Item = Priority>,
+    {
+        self.priorities.extend(priorities);
+        self
+    }
+
+    /// Only return messages with **all** of these tags
+    pub fn tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags.extend(tags.into_iter().map(|t| t.into()));
+        self
+    }
+
+    /// Check if no condition is set
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none()
+            && self.message.is_none()
+            && self.title.is_none()
+            && self.priorities.is_empty()
+            && self.tags.is_empty()
+    }
+
+    /// Query parameters of the subscription URL
+    pub(crate) fn query_pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = Vec::new();
+
+        if let Some(id) = &self.id {
+            pairs.push(("id", id.clone()));
+        }
+
+        if let Some(message) = &self.message {
+            pairs.push(("message", message.clone()));
+        }
+
+        if let Some(title) = &self.title {
+            pairs.push(("title", title.clone()));
+        }
+
+        if !self.priorities.is_empty() {
+            let priorities: Vec<String> = self
+                .priorities
+                .iter()
+                .map(|p| p.as_u8().to_string())
+                .collect();
+            pairs.push(("priority", priorities.join(",")));
+        }
+
+        if !self.tags.is_empty() {
+            pairs.push(("tags", self.tags.join(",")));
+        }
+
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_pairs() {
+        assert!(SubscriptionFilter::new().query_pairs().is_empty());
+
+        let filter = SubscriptionFilter::new()
+            .priorities([Priority::Max, Priority::High])
+            .tags(["backup", "failed"])
+            .title("Backup");
+        assert_eq!(
+            filter.query_pairs(),
+            vec![
+                ("title", String::from("Backup")),
+                ("priority", String::from("5,4")),
+                ("tags", String::from("backup,failed")),
+            ]
+        );
+    }
+}
@@ -5,14 +5,34 @@ mod r#async;
 #[cfg(feature = "blocking-subscriber")]
 mod blocking;
 pub mod builder;
+pub mod event;
+pub mod filter;
+mod net;
+pub mod reconnect;
 mod request;
+pub mod since;
+pub mod transport;
+mod watchdog;
 
 #[cfg(feature = "async-subscriber")]
-pub use self::r#async::{Async, MessageStream as AsyncMessageStream};
+pub use self::r#async::{
+    Async, MessageStream as AsyncMessageStream,
+    ReconnectingMessageStream as AsyncReconnectingMessageStream,
+};
 #[cfg(feature = "blocking-subscriber")]
-pub use self::blocking::{Blocking, MessageStream as BlockingMessageStream};
+pub use self::blocking::{
+    Blocking, MessageStream as BlockingMessageStream,
+    ReconnectingMessageStream as BlockingReconnectingMessageStream,
+};
 pub use self::builder::SubscriberBuilder;
+pub use self::event::{Event, Message};
+pub use self::filter::SubscriptionFilter;
+pub use self::reconnect::ReconnectPolicy;
+pub use self::since::Since;
+pub use self::transport::{SubscribeTransport, Transport};
 use crate::error::Error;
+use crate::payload::ReceivedPayload;
+use crate::util;
 
 /// Creates a [`SubscriberBuilder`]
 #[inline]
@@ -23,6 +43,31 @@ where
     SubscriberBuilder::new(url)
 }
 
+/// Validate the topic names and join them as comma-separated list
+fn join_topics<I, S>(topics: I) -> Result<String, Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut joined = String::new();
+
+    for topic in topics.into_iter() {
+        let topic: &str = topic.as_ref();
+        util::validate_topic(topic)?;
+
+        if !joined.is_empty() {
+            joined.push(',');
+        }
+        joined.push_str(topic);
+    }
+
+    if joined.is_empty() {
+        return Err(Error::InvalidTopic(joined));
+    }
+
+    Ok(joined)
+}
+
 #[derive(Debug, Clone)]
 pub struct Subscriber<T>
 where
@@ -32,26 +77,89 @@ where
     inner: T,
 }
 
-#[cfg(feature = "async-subscriber")]
-impl Subscriber<Async> {
+impl<T> Subscriber<T>
+where
+    T: SubscribeTransport,
+{
     /// Subscribe to ntfy server topic
     #[inline]
-    pub async fn subscribe<S>(&self, topic: S) -> Result<AsyncMessageStream, Error>
+    pub fn subscribe<S>(&self, topic: S) -> T::Output<'_, T::Stream>
     where
+        S: Into<String>,
+    {
+        self.inner.subscribe(&self.url, topic.into())
+    }
+
+    /// Subscribe to multiple ntfy server topics over a single connection
+    ///
+    /// The topic of each received message is available in [`ReceivedPayload::topic`](crate::payload::ReceivedPayload::topic).
+    ///
+    /// <https://docs.ntfy.sh/subscribe/api/#subscribe-to-multiple-topics>
+    pub fn subscribe_many<I, S>(&self, topics: I) -> T::Output<'_, T::Stream>
+    where
+        I: IntoIterator<Item = S>,
         S: AsRef<str>,
     {
-        self.inner.subscribe(&self.url, topic.as_ref()).await
+        match join_topics(topics) {
+            Ok(topics) => self.inner.subscribe(&self.url, topics),
+            Err(e) => T::fail(e),
+        }
     }
-}
 
-#[cfg(feature = "blocking-subscriber")]
-impl Subscriber<Blocking> {
-    /// Subscribe to ntfy server topic
+    /// Fetch the cached messages of the topic, without keeping a connection open
+    ///
+    /// <https://docs.ntfy.sh/subscribe/api/#poll-for-messages>
     #[inline]
-    pub fn subscribe<S>(&self, topic: S) -> Result<BlockingMessageStream, Error>
+    pub fn poll<S>(&self, topic: S, since: Since) -> T::Output<'_, Vec<ReceivedPayload>>
     where
-        S: AsRef<str>,
+        S: Into<String>,
     {
-        self.inner.subscribe(&self.url, topic.as_ref())
+        self.inner.poll(&self.url, topic.into(), since, false)
+    }
+
+    /// Fetch the cached messages of the topic, including the scheduled (delayed) ones
+    /// that have not been delivered yet
+    ///
+    /// <https://docs.ntfy.sh/subscribe/api/#list-scheduled-messages>
+    #[inline]
+    pub fn poll_scheduled<S>(&self, topic: S, since: Since) -> T::Output<'_, Vec<ReceivedPayload>>
+    where
+        S: Into<String>,
+    {
+        self.inner.poll(&self.url, topic.into(), since, true)
+    }
+
+    /// Subscribe to ntfy server topic, automatically reconnecting when the connection is lost
+    ///
+    /// After reconnecting, the subscription resumes from the last received message (`since=<id>`),
+    /// so no messages are missed or duplicated.
+    /// The first connection attempt isn't retried.
+    #[inline]
+    pub fn subscribe_with_reconnect<S>(
+        &self,
+        topic: S,
+        policy: ReconnectPolicy,
+    ) -> T::Output<'_, T::ReconnectingStream>
+    where
+        S: Into<String>,
+    {
+        self.inner
+            .subscribe_with_reconnect(&self.url, topic.into(), policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join_topics() {
+        assert_eq!(join_topics(["alerts"]).unwrap(), "alerts");
+        assert_eq!(
+            join_topics(vec!["alerts", "backups"]).unwrap(),
+            "alerts,backups"
+        );
+        assert!(join_topics(Vec::<String>::new()).is_err());
+        assert!(join_topics(["alerts", "back/ups"]).is_err());
     }
 }
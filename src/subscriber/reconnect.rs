@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use super::since::Since;
+use crate::error::Error;
+use crate::payload::{ReceivedMessageType, ReceivedPayload};
+use crate::util;
+
+/// Reconnect policy with exponential backoff
+///
+/// By default, the subscriber reconnects forever, starting with a backoff of 1 sec up to 60 secs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    max_attempts: Option<u32>,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: None,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            jitter: true,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// New default reconnect policy
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Max number of consecutive failed reconnection attempts (default: unlimited)
+    #[inline]
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Backoff of the first attempt, doubled at every attempt up to `max` (default: 1 sec and 60 secs)
+    #[inline]
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Randomize the backoff between 50% and 100% of its value (default: true)
+    #[inline]
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Check if another attempt is allowed after `failures` consecutive failures
+    pub(crate) fn can_retry(&self, failures: u32) -> bool {
+        match self.max_attempts {
+            Some(max) => failures < max,
+            None => true,
+        }
+    }
+
+    /// Time to wait before the attempt following `failures` consecutive failures
+    pub(crate) fn backoff_for(&self, failures: u32) -> Duration {
+        let backoff: Duration = util::backoff(
+            self.initial_backoff,
+            self.max_backoff,
+            failures.saturating_sub(1),
+        );
+
+        if self.jitter {
+            util::jitter(backoff)
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Position in the topic, used to resume the subscription without losing messages
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Cursor {
+    /// ID of the last received message
+    last_id: Option<String>,
+    /// Time of the last received event
+    last_time: Option<u64>,
+}
+
+impl Cursor {
+    pub(crate) fn update(&mut self, payload: &ReceivedPayload) {
+        match payload.event {
+            // Not cached by the server, so can't be used for `since`
+            ReceivedMessageType::Open
+            | ReceivedMessageType::Keepalive
+            | ReceivedMessageType::PollRequest => {}
+            _ => self.last_id = Some(payload.id.clone()),
+        }
+
+        self.last_time = Some(payload.time);
+    }
+
+    /// Value of the `since` parameter
+    pub(crate) fn since(&self) -> Option<Since> {
+        match (&self.last_id, self.last_time) {
+            (Some(id), _) => Some(Since::Id(id.clone())),
+            (None, Some(time)) => Some(Since::Unix(time)),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Check if a failed connection attempt can be retried
+///
+/// Requests rejected by the server (i.e. unauthorized) will fail again.
+pub(crate) fn is_recoverable(error: &Error) -> bool {
+    match error {
+        Error::Server { status, .. } => *status == 429 || *status >= 500,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor() {
+        let mut cursor = Cursor::default();
+        assert_eq!(cursor.since(), None);
+
+        let open: ReceivedPayload = serde_json::from_str(
+            r#"{"id":"2pfJQj3SVmWr","time":1643935900,"event":"open","topic":"mytopic"}"#,
+        )
+        .unwrap();
+        cursor.update(&open);
+        assert_eq!(cursor.since(), Some(Since::Unix(1643935900)));
+
+        let message: ReceivedPayload = serde_json::from_str(
+            r#"{"id":"sPs71M8A2T","time":1643935928,"event":"message","topic":"mytopic","message":"Hi"}"#,
+        )
+        .unwrap();
+        cursor.update(&message);
+        cursor.update(&open);
+        assert_eq!(cursor.since(), Some(Since::id("sPs71M8A2T")));
+    }
+}
@@ -1,17 +1,46 @@
 use tungstenite::client::ClientRequestBuilder;
 use url::Url;
 
+use super::filter::SubscriptionFilter;
+use super::since::Since;
 use crate::Error;
 use crate::auth::Auth;
 
-pub(crate) fn get_request_builder(
+/// Build the URL of a topic subscription endpoint (i.e. `ws`, `json`)
+pub(crate) fn topic_url(
     url: &Url,
     topic: &str,
-    auth: &Option<Auth>,
-) -> Result<ClientRequestBuilder, Error> {
-    let path: String = format!("{topic}/ws");
-    let url: Url = url.join(&path)?;
+    endpoint: &str,
+    since: Option<&Since>,
+    filter: &SubscriptionFilter,
+) -> Result<Url, Error> {
+    let path: String = format!("{topic}/{endpoint}");
+    let mut url: Url = url.join(&path)?;
 
+    if let Some(since) = since {
+        url.query_pairs_mut()
+            .append_pair("since", &since.to_string());
+    }
+
+    if !filter.is_empty() {
+        url.query_pairs_mut().extend_pairs(filter.query_pairs());
+    }
+
+    Ok(url)
+}
+
+/// Convert the WebSocket URL to the HTTP one
+pub(crate) fn http_url(url: &Url) -> Url {
+    let mut url: Url = url.clone();
+    let scheme: &str = match url.scheme() {
+        "wss" => "https",
+        _ => "http",
+    };
+    url.set_scheme(scheme).expect("Valid scheme");
+    url
+}
+
+pub(crate) fn get_request_builder(url: &Url, auth: &Option<Auth>) -> ClientRequestBuilder {
     let uri = url.to_string().parse::<http::Uri>().unwrap();
 
     let mut builder = ClientRequestBuilder::new(uri);
@@ -20,5 +49,16 @@ pub(crate) fn get_request_builder(
         builder = builder.with_header("Authorization", auth.header_value());
     }
 
-    Ok(builder)
+    builder
+}
+
+/// Parse the newline-delimited JSON messages
+pub(crate) fn parse_json_lines<T>(text: &str) -> Result<Vec<T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
 }
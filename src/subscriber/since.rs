@@ -0,0 +1,59 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Starting point of a subscription or poll
+///
+/// <https://docs.ntfy.sh/subscribe/api/#fetch-cached-messages>
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Since {
+    /// All cached messages
+    All,
+    /// Only the most recent cached message
+    Latest,
+    /// Messages published after the message with this ID
+    Id(String),
+    /// Messages published after this Unix time stamp
+    Unix(u64),
+    /// Messages published in the last duration (seconds precision)
+    Duration(Duration),
+}
+
+impl fmt::Display for Since {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::Latest => write!(f, "latest"),
+            Self::Id(id) => write!(f, "{id}"),
+            Self::Unix(timestamp) => write!(f, "{timestamp}"),
+            Self::Duration(duration) => write!(f, "{}s", duration.as_secs()),
+        }
+    }
+}
+
+impl Since {
+    /// Messages published after the message with this ID
+    #[inline]
+    pub fn id<S>(id: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self::Id(id.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_since_query_value() {
+        assert_eq!(Since::All.to_string(), "all");
+        assert_eq!(Since::Latest.to_string(), "latest");
+        assert_eq!(Since::id("sPs71M8A2T").to_string(), "sPs71M8A2T");
+        assert_eq!(Since::Unix(1643935928).to_string(), "1643935928");
+        assert_eq!(
+            Since::Duration(Duration::from_secs(600)).to_string(),
+            "600s"
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use url::Url;
+
+#[cfg(feature = "async-subscriber")]
+use super::r#async::{self, Async};
+#[cfg(feature = "blocking-subscriber")]
+use super::blocking::{self, Blocking};
+use super::reconnect::ReconnectPolicy;
+use super::since::Since;
+use crate::error::Error;
+use crate::payload::ReceivedPayload;
+#[cfg(feature = "async-subscriber")]
+use crate::util::BoxedFuture;
+
+/// Transport used by the [`Subscriber`](super::Subscriber) to receive the messages
+///
+/// Implemented by [`Async`] (reqwest and tokio-tungstenite), [`Blocking`] (ureq and tungstenite)
+/// and [`Memory`](crate::memory::Memory).
+/// Custom transports can be used with [`SubscriberBuilder::build_with_transport`](super::SubscriberBuilder::build_with_transport).
+pub trait SubscribeTransport: Clone {
+    /// `Result` for blocking transports, `Future` resolving to a `Result` for the async ones
+    type Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a;
+
+    /// Stream of the received messages
+    type Stream;
+
+    /// Stream of the received messages, reconnecting when the connection is lost
+    type ReconnectingStream;
+
+    /// Subscribe to the topics (comma-separated list)
+    fn subscribe<'a>(&'a self, url: &'a Url, topics: String) -> Self::Output<'a, Self::Stream>;
+
+    /// Subscribe to the topic, reconnecting according to the policy
+    fn subscribe_with_reconnect<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        policy: ReconnectPolicy,
+    ) -> Self::Output<'a, Self::ReconnectingStream>;
+
+    /// Fetch the cached messages of the topic
+    fn poll<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        since: Since,
+        scheduled: bool,
+    ) -> Self::Output<'a, Vec<ReceivedPayload>>;
+
+    /// Output of a request that failed before reaching the transport (i.e. invalid topics)
+    fn fail<'a, T>(error: Error) -> Self::Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a;
+}
+
+#[cfg(feature = "async-subscriber")]
+impl SubscribeTransport for Async {
+    type Output<'a, T>
+        = BoxedFuture<'a, Result<T, Error>>
+    where
+        Self: 'a,
+        T: 'a;
+
+    type Stream = r#async::MessageStream;
+
+    type ReconnectingStream = r#async::ReconnectingMessageStream;
+
+    fn subscribe<'a>(&'a self, url: &'a Url, topics: String) -> Self::Output<'a, Self::Stream> {
+        Box::pin(async move { Async::subscribe(self, url, &topics).await })
+    }
+
+    fn subscribe_with_reconnect<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        policy: ReconnectPolicy,
+    ) -> Self::Output<'a, Self::ReconnectingStream> {
+        Box::pin(async move { Async::subscribe_with_reconnect(self, url, &topic, policy).await })
+    }
+
+    fn poll<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        since: Since,
+        scheduled: bool,
+    ) -> Self::Output<'a, Vec<ReceivedPayload>> {
+        Box::pin(async move { Async::poll(self, url, &topic, &since, scheduled).await })
+    }
+
+    fn fail<'a, T>(error: Error) -> Self::Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a,
+    {
+        Box::pin(async move { Err(error) })
+    }
+}
+
+#[cfg(feature = "blocking-subscriber")]
+impl SubscribeTransport for Blocking {
+    type Output<'a, T>
+        = Result<T, Error>
+    where
+        Self: 'a,
+        T: 'a;
+
+    type Stream = blocking::MessageStream;
+
+    type ReconnectingStream = blocking::ReconnectingMessageStream;
+
+    #[inline]
+    fn subscribe<'a>(&'a self, url: &'a Url, topics: String) -> Self::Output<'a, Self::Stream> {
+        Blocking::subscribe(self, url, &topics)
+    }
+
+    #[inline]
+    fn subscribe_with_reconnect<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        policy: ReconnectPolicy,
+    ) -> Self::Output<'a, Self::ReconnectingStream> {
+        Blocking::subscribe_with_reconnect(self, url, &topic, policy)
+    }
+
+    #[inline]
+    fn poll<'a>(
+        &'a self,
+        url: &'a Url,
+        topic: String,
+        since: Since,
+        scheduled: bool,
+    ) -> Self::Output<'a, Vec<ReceivedPayload>> {
+        Blocking::poll(self, url, &topic, &since, scheduled)
+    }
+
+    #[inline]
+    fn fail<'a, T>(error: Error) -> Self::Output<'a, T>
+    where
+        Self: 'a,
+        T: 'a,
+    {
+        Err(error)
+    }
+}
+
+/// Subscription transport
+///
+/// <https://docs.ntfy.sh/subscribe/api/>
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Transport {
+    /// WebSocket (`/<topic>/ws`)
+    #[default]
+    WebSocket,
+    /// HTTP stream of newline-delimited JSON messages (`/<topic>/json`)
+    ///
+    /// Useful behind proxies that block the WebSocket upgrade.
+    Json,
+    /// HTTP Server-Sent Events (`/<topic>/sse`)
+    ///
+    /// Useful behind proxies that block the WebSocket upgrade.
+    Sse,
+}
+
+impl Transport {
+    /// Endpoint of the topic (i.e. `ws` for `/<topic>/ws`)
+    pub(crate) fn endpoint(&self) -> &'static str {
+        match self {
+            Self::WebSocket => "ws",
+            Self::Json => "json",
+            Self::Sse => "sse",
+        }
+    }
+
+    /// Decode a line received from an HTTP stream
+    ///
+    /// Returns `None` if the line doesn't carry a message (i.e. SSE `event:` lines).
+    pub(crate) fn decode_line(&self, line: &[u8]) -> Option<Result<ReceivedPayload, Error>> {
+        let line = String::from_utf8_lossy(line);
+        let line: &str = line.trim();
+
+        let data: &str = match self {
+            Self::Sse => line.strip_prefix("data:")?.trim_start(),
+            _ => line,
+        };
+
+        if data.is_empty() {
+            return None;
+        }
+
+        Some(serde_json::from_str(data).map_err(Error::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::ReceivedMessageType;
+
+    #[test]
+    fn test_decode_line() {
+        let json = br#"{"id":"hwQ2YpKdmg","time":1635528741,"event":"message","topic":"mytopic","message":"Disk full"}"#;
+        let payload = Transport::Json.decode_line(json).unwrap().unwrap();
+        assert_eq!(payload.event, ReceivedMessageType::Message);
+        assert_eq!(payload.message.as_deref(), Some("Disk full"));
+
+        assert!(Transport::Json.decode_line(b"\n").is_none());
+
+        assert!(Transport::Sse.decode_line(b"event: keepalive").is_none());
+        let sse =
+            br#"data: {"id":"VNxNIg5fpt","time":1635528757,"event":"keepalive","topic":"mytopic"}"#;
+        let payload = Transport::Sse.decode_line(sse).unwrap().unwrap();
+        assert_eq!(payload.event, ReceivedMessageType::Keepalive);
+    }
+}
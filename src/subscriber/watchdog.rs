@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+
+/// Connection liveness tracking
+///
+/// Detects half-open connections, on which nothing is received anymore,
+/// and schedules the WebSocket pings.
+#[derive(Debug, Clone)]
+pub(crate) struct Watchdog {
+    read_timeout: Option<Duration>,
+    keepalive_timeout: Option<Duration>,
+    ping_interval: Option<Duration>,
+    last_read: Instant,
+    last_event: Instant,
+    last_ping: Instant,
+}
+
+impl Watchdog {
+    pub(crate) fn new(
+        read_timeout: Option<Duration>,
+        keepalive_timeout: Option<Duration>,
+        ping_interval: Option<Duration>,
+    ) -> Self {
+        let now: Instant = Instant::now();
+        Self {
+            read_timeout,
+            keepalive_timeout,
+            ping_interval,
+            last_read: now,
+            last_event: now,
+            last_ping: now,
+        }
+    }
+
+    /// Some data has been received (i.e. a WebSocket control frame)
+    #[inline]
+    pub(crate) fn read(&mut self) {
+        self.last_read = Instant::now();
+    }
+
+    /// An event has been received, `keepalive` included
+    #[inline]
+    pub(crate) fn event(&mut self) {
+        self.last_event = Instant::now();
+        self.last_read = self.last_event;
+    }
+
+    #[inline]
+    pub(crate) fn last_event(&self) -> Instant {
+        self.last_event
+    }
+
+    /// Check if the connection must be considered dead
+    pub(crate) fn check(&self, now: Instant) -> Result<(), Error> {
+        if let Some(timeout) = self.keepalive_timeout {
+            if now.duration_since(self.last_event) >= timeout {
+                return Err(Error::KeepaliveTimeout);
+            }
+        }
+
+        if let Some(timeout) = self.read_timeout {
+            if now.duration_since(self.last_read) >= timeout {
+                return Err(Error::Timeout);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check if a ping must be sent, marking it as sent
+    pub(crate) fn ping(&mut self, now: Instant) -> bool {
+        match self.ping_interval {
+            Some(interval) if now.duration_since(self.last_ping) >= interval => {
+                self.last_ping = now;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Next time something must be checked
+    #[cfg(feature = "async-subscriber")]
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        [
+            self.read_timeout.map(|t| self.last_read + t),
+            self.keepalive_timeout.map(|t| self.last_event + t),
+            self.ping_interval.map(|t| self.last_ping + t),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Max time to block on a read, before checking again
+    #[cfg(feature = "blocking-subscriber")]
+    pub(crate) fn tick(&self) -> Option<Duration> {
+        [
+            self.read_timeout,
+            self.keepalive_timeout,
+            self.ping_interval,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog() {
+        let mut watchdog = Watchdog::new(
+            Some(Duration::from_secs(10)),
+            Some(Duration::from_secs(60)),
+            Some(Duration::from_secs(30)),
+        );
+        let start: Instant = watchdog.last_event();
+
+        assert!(watchdog.check(start).is_ok());
+        #[cfg(feature = "async-subscriber")]
+        assert_eq!(watchdog.deadline(), Some(start + Duration::from_secs(10)));
+
+        assert!(matches!(
+            watchdog.check(start + Duration::from_secs(10)),
+            Err(Error::Timeout)
+        ));
+        assert!(matches!(
+            watchdog.check(start + Duration::from_secs(60)),
+            Err(Error::KeepaliveTimeout)
+        ));
+
+        assert!(!watchdog.ping(start + Duration::from_secs(20)));
+        assert!(watchdog.ping(start + Duration::from_secs(30)));
+        assert!(!watchdog.ping(start + Duration::from_secs(40)));
+
+        watchdog.event();
+        assert!(watchdog.last_event() >= start);
+    }
+}
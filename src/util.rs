@@ -0,0 +1,82 @@
+// Copyright (c) 2022 Yuki Kishimoto
+// Distributed under the MIT software license
+
+use std::collections::hash_map::RandomState;
+#[cfg(any(feature = "async-dispatcher", feature = "async-subscriber"))]
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+#[cfg(any(feature = "async-dispatcher", feature = "async-subscriber"))]
+use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+
+/// Max length of a topic name
+pub(crate) const TOPIC_MAX_LEN: usize = 64;
+
+/// Future returned by the async transports
+#[cfg(all(
+    any(feature = "async-dispatcher", feature = "async-subscriber"),
+    not(target_arch = "wasm32")
+))]
+pub(crate) type BoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Future returned by the async transports
+#[cfg(all(
+    any(feature = "async-dispatcher", feature = "async-subscriber"),
+    target_arch = "wasm32"
+))]
+pub(crate) type BoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Convert Unix time stamp (secs) to [`SystemTime`]
+#[inline]
+pub(crate) fn unix_time(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// Exponential backoff: `initial * 2^attempt`, capped to `max`
+pub(crate) fn backoff(initial: Duration, max: Duration, attempt: u32) -> Duration {
+    initial
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(max)
+}
+
+/// Randomize the duration between 50% and 100% of its value
+pub(crate) fn jitter(duration: Duration) -> Duration {
+    let random: u64 = RandomState::new().build_hasher().finish();
+    let factor: f64 = 0.5 + (random % 1000) as f64 / 2000.0;
+    duration.mul_f64(factor)
+}
+
+/// Check that the topic name matches `[-_A-Za-z0-9]{1,64}`
+pub(crate) fn validate_topic(topic: &str) -> Result<(), Error> {
+    let valid: bool = !topic.is_empty()
+        && topic.len() <= TOPIC_MAX_LEN
+        && topic
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidTopic(topic.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_topic() {
+        assert!(validate_topic("mytopic").is_ok());
+        assert!(validate_topic("my-topic_123").is_ok());
+        assert!(validate_topic(&"a".repeat(64)).is_ok());
+
+        assert!(validate_topic("").is_err());
+        assert!(validate_topic("my topic").is_err());
+        assert!(validate_topic("a,b").is_err());
+        assert!(validate_topic("tópico").is_err());
+        assert!(validate_topic(&"a".repeat(65)).is_err());
+    }
+}